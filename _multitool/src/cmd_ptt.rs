@@ -1,4 +1,7 @@
 use crate::utils;
+use overlay_protocol::command::DrawCommand;
+use overlay_protocol::coord::Coord;
+use overlay_protocol::draw::RectangleParams;
 pub fn run(state: &str) {
     let (vol, outline_color) = match state {
         "1" => ("0.4", "0xFFFFFFFF"),
@@ -6,18 +9,8 @@ pub fn run(state: &str) {
         _ => return,
     };
     utils::exec_silent("wpctl", &["set-volume", "@DEFAULT_AUDIO_SOURCE@", vol]);
-    let command = utils::OverlayCommand {
-        layer: None,
-        timeout_ms: None,
-        operations: vec![utils::DrawOperation::Rectangle(utils::RectangleParams {
-            x1: 1,
-            y1: 1,
-            x2: 1920,
-            y2: 1080,
-            fill_color: "0x00000000".to_string(),
-            outline_width: 5.0,
-            outline_color: outline_color.to_string(),
-        })],
-    };
-    utils::send_overlay_command(&command);
+    let full = Coord::percent(100.0);
+    let command =
+        DrawCommand::new().push(RectangleParams::new(0, 0, full, full).outline(5.0, outline_color));
+    utils::send_overlay_command(command);
 }
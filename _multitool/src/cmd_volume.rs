@@ -1,4 +1,7 @@
 use crate::utils::{self, PwNode};
+use overlay_protocol::command::DrawCommand;
+use overlay_protocol::coord::Coord;
+use overlay_protocol::draw::{LineParams, RectangleParams};
 use regex::Regex;
 fn get_physical_sink_ids(nodes: &[PwNode]) -> Vec<u32> {
     let mut ids = Vec::new();
@@ -54,45 +57,19 @@ pub fn run(arg: &str) -> Option<()> {
         utils::exec_silent("wpctl", &["set-volume", &id.to_string(), &vol_str]);
     }
     let display_vol = new_vol.min(1.0);
-    let bar_height = (1080.0 * display_vol) as i32;
-    let mut operations = vec![
-        utils::DrawOperation::Rectangle(utils::RectangleParams {
-            x1: 0,
-            y1: 1080 - bar_height,
-            x2: 10,
-            y2: 1080,
-            fill_color: "0xFFFFFFFF".to_string(),
-            outline_width: 0.0,
-            outline_color: "0x00000000".to_string(),
-        }),
-        utils::DrawOperation::Rectangle(utils::RectangleParams {
-            x1: 1910,
-            y1: 1080 - bar_height,
-            x2: 1920,
-            y2: 1080,
-            fill_color: "0xFFFFFFFF".to_string(),
-            outline_width: 0.0,
-            outline_color: "0x00000000".to_string(),
-        }),
-    ];
-    let segment_height = 1080 / 5;
+    let bar_top = Coord::percent(100.0 - display_vol * 100.0);
+    let full = Coord::percent(100.0);
+    let mut command = DrawCommand::new()
+        .layer(1)
+        .timeout_ms(1000)
+        .push(RectangleParams::new(0, bar_top, 10, full).fill("0xFFFFFFFF"))
+        .push(
+            RectangleParams::new(Coord::new(100.0, -10.0), bar_top, full, full).fill("0xFFFFFFFF"),
+        );
     for i in 1..5 {
-        let y = i * segment_height;
-        operations.push(utils::DrawOperation::Line(utils::LineParams {
-            x1: 0,
-            y1: y,
-            x2: 1920,
-            y2: y,
-            width: 1.0,
-            side: utils::LineSide::Center,
-            color: "0xFFFFFFFF".to_string(),
-        }));
+        let y = Coord::percent(i as f32 * 20.0);
+        command = command.push(LineParams::new(0, y, full, y));
     }
-    let command = utils::OverlayCommand {
-        layer: Some(1),
-        timeout_ms: Some(1000),
-        operations,
-    };
-    utils::send_overlay_command(&command);
+    utils::send_overlay_command(command);
     Some(())
 }
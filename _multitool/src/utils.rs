@@ -1,49 +1,11 @@
-use serde::{Deserialize, Serialize};
+use overlay_protocol::OverlayClient;
+use serde::Deserialize;
 use std::io::Write;
-use std::net::UdpSocket;
 use std::process::{Command, Stdio};
 pub const VIRTUAL_SINK_TO_CYCLE: &str = "X1";
 pub const IGNORED_SINKS: &[&str] = &["X1", "X2"];
 pub const PTT_UDP_ADDR: &str = "127.0.0.1:7435";
 pub const NOTIFY_WAV: &[u8] = include_bytes!("../assets/notify.wav");
-#[derive(Serialize)]
-pub struct OverlayCommand {
-    pub layer: Option<i32>,
-    pub timeout_ms: Option<u64>,
-    pub operations: Vec<DrawOperation>,
-}
-#[derive(Serialize)]
-pub enum DrawOperation {
-    Rectangle(RectangleParams),
-    Line(LineParams),
-}
-#[derive(Serialize)]
-pub struct RectangleParams {
-    pub x1: i32,
-    pub y1: i32,
-    pub x2: i32,
-    pub y2: i32,
-    pub fill_color: String,
-    pub outline_width: f32,
-    pub outline_color: String,
-}
-#[derive(Serialize)]
-pub struct LineParams {
-    pub x1: i32,
-    pub y1: i32,
-    pub x2: i32,
-    pub y2: i32,
-    pub width: f32,
-    pub side: LineSide,
-    pub color: String,
-}
-#[derive(Serialize)]
-#[allow(dead_code)]
-pub enum LineSide {
-    Left,
-    Right,
-    Center,
-}
 #[derive(Deserialize)]
 pub struct PwNode {
     pub id: u32,
@@ -93,10 +55,8 @@ pub fn get_all_sinks() -> Option<Vec<PwNode>> {
     let json_str = exec_output("pw-dump", &[])?;
     serde_json::from_str(&json_str).ok()
 }
-pub fn send_overlay_command(command: &OverlayCommand) {
-    if let Ok(json) = serde_json::to_string(command) {
-        if let Ok(socket) = UdpSocket::bind("0.0.0.0:0") {
-            let _ = socket.send_to(json.as_bytes(), PTT_UDP_ADDR);
-        }
+pub fn send_overlay_command(command: impl Into<overlay_protocol::Command>) {
+    if let Ok(mut client) = OverlayClient::udp(PTT_UDP_ADDR) {
+        let _ = client.send(command);
     }
 }
@@ -39,4 +39,4 @@ fn main() {
             sleep(Duration::from_secs(1) - elapsed);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,356 @@
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use overlay_protocol::auth::load_secret;
+use overlay_protocol::command::{Command, DrawCommand, LayerAction, Query, Subscription};
+use overlay_protocol::config::Listeners;
+use overlay_protocol::coord::{Anchor, Coord};
+use overlay_protocol::draw::{
+    CircleParams, DrawOperation, LineParams, RectangleParams, TextParams,
+};
+use overlay_protocol::tls::TlsOptions;
+use overlay_protocol::{OverlayClient, Response};
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+#[derive(Parser)]
+#[command(author, version, about)]
+struct Args {
+    /// Unix socket of the running overlay.
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+    /// UDP address of the running overlay, e.g. `127.0.0.1:7435`. Commands sent over UDP get
+    /// no reply.
+    #[arg(long, global = true, conflicts_with = "socket")]
+    udp: Option<SocketAddr>,
+    /// TCP address of an overlay on another machine.
+    #[arg(long, global = true, conflicts_with_all = ["socket", "udp"])]
+    tcp: Option<SocketAddr>,
+    /// PEM CA certificate to verify the overlay's TLS certificate with; enables TLS.
+    #[arg(long, global = true, conflicts_with_all = ["socket", "udp"])]
+    tls_ca: Option<PathBuf>,
+    /// Name the overlay's certificate is checked against, by default the TCP address.
+    #[arg(long, global = true, requires = "tls_ca")]
+    tls_server_name: Option<String>,
+    /// PEM client certificate, for overlays that require one.
+    #[arg(long, global = true, requires_all = ["tls_ca", "tls_key"])]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-cert.
+    #[arg(long, global = true, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// File with the shared secret that UDP commands are signed with.
+    #[arg(long, global = true)]
+    secret_file: Option<PathBuf>,
+    /// Overlay config to find the socket, TCP address or UDP port in, by default
+    /// `$XDG_CONFIG_HOME/overlay/config.toml`.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Cmd,
+}
+#[derive(Subcommand)]
+enum Cmd {
+    /// Send JSON commands from a file, or from stdin for `-`.
+    Send {
+        #[arg(default_value = "-")]
+        file: String,
+    },
+    /// Draw a rectangle.
+    Rect {
+        #[arg(long, allow_hyphen_values = true)]
+        x1: Coord,
+        #[arg(long, allow_hyphen_values = true)]
+        y1: Coord,
+        #[arg(long, allow_hyphen_values = true)]
+        x2: Coord,
+        #[arg(long, allow_hyphen_values = true)]
+        y2: Coord,
+        #[command(flatten)]
+        style: ShapeStyle,
+        #[command(flatten)]
+        layer: LayerArgs,
+    },
+    /// Draw a circle around `x`, `y`.
+    Circle {
+        #[arg(long, allow_hyphen_values = true)]
+        x: Coord,
+        #[arg(long, allow_hyphen_values = true)]
+        y: Coord,
+        #[arg(long)]
+        radius: f32,
+        #[command(flatten)]
+        style: ShapeStyle,
+        #[command(flatten)]
+        layer: LayerArgs,
+    },
+    /// Draw a line.
+    Line {
+        #[arg(long, allow_hyphen_values = true)]
+        x1: Coord,
+        #[arg(long, allow_hyphen_values = true)]
+        y1: Coord,
+        #[arg(long, allow_hyphen_values = true)]
+        x2: Coord,
+        #[arg(long, allow_hyphen_values = true)]
+        y2: Coord,
+        #[arg(long, default_value_t = 1.0)]
+        width: f32,
+        #[arg(long, default_value = "0xFFFFFFFF")]
+        color: String,
+        #[command(flatten)]
+        layer: LayerArgs,
+    },
+    /// Draw text with its top left corner at `x`, `y`.
+    Text {
+        text: String,
+        #[arg(long, allow_hyphen_values = true)]
+        x: Coord,
+        #[arg(long, allow_hyphen_values = true)]
+        y: Coord,
+        #[arg(long, default_value_t = 16.0)]
+        size: f32,
+        #[arg(long, default_value = "0xFFFFFFFF")]
+        color: String,
+        /// Font family name, or a path to a font file.
+        #[arg(long)]
+        font: Option<String>,
+        #[command(flatten)]
+        layer: LayerArgs,
+    },
+    /// Remove a layer, or every layer with `--all`.
+    #[command(group = clap::ArgGroup::new("target").required(true))]
+    Clear {
+        #[arg(group = "target")]
+        layer: Option<i32>,
+        #[arg(long, group = "target")]
+        all: bool,
+    },
+    /// List the layers on screen.
+    Layers,
+    /// Print the operations of a layer.
+    Dump { layer: i32 },
+    /// List the surfaces and their sizes.
+    Surfaces,
+    /// Print render statistics.
+    Stats,
+    /// Write the rendered surface to the overlay's `--snapshot` PNG file.
+    Snapshot {
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Print events as JSON lines until interrupted.
+    Events,
+}
+#[derive(clap::Args)]
+struct ShapeStyle {
+    #[arg(long, default_value = "0x00000000")]
+    fill: String,
+    #[arg(long, default_value_t = 0.0)]
+    outline_width: f32,
+    #[arg(long, default_value = "0x00000000")]
+    outline: String,
+}
+#[derive(clap::Args)]
+struct LayerArgs {
+    #[arg(long)]
+    layer: Option<i32>,
+    /// Remove the layer after this many milliseconds.
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// `all`, `focused` or an output name.
+    #[arg(long)]
+    output: Option<String>,
+    #[arg(long, value_parser = parse_anchor)]
+    anchor: Option<Anchor>,
+}
+impl LayerArgs {
+    fn command(self, operation: impl Into<DrawOperation>) -> DrawCommand {
+        let mut command = DrawCommand::new().push(operation);
+        command.layer = self.layer;
+        command.timeout_ms = self.timeout;
+        command.output = self.output.map(Into::into);
+        command.anchor = self.anchor.unwrap_or_default();
+        command
+    }
+}
+fn parse_anchor(text: &str) -> Result<Anchor, String> {
+    serde_json::from_value(serde_json::Value::String(text.to_string()))
+        .map_err(|_| format!("invalid anchor '{}'", text))
+}
+/// Connects to the socket, TCP address or UDP port given on the command line, or else to
+/// the ones in the overlay's config, preferring the socket and then TCP.
+fn connect(args: &Args) -> Result<OverlayClient> {
+    if let Some(addr) = args.tcp {
+        return connect_tcp(args, addr);
+    }
+    let config = Listeners::load(args.config.as_deref())?;
+    let config_socket = config.socket.map(PathBuf::from);
+    let socket = args
+        .socket
+        .as_ref()
+        .or(config_socket.as_ref().filter(|_| args.udp.is_none()));
+    if let Some(path) = socket {
+        return OverlayClient::unix(path)
+            .with_context(|| format!("failed to connect to {}", path.display()));
+    }
+    if let Some(mut addr) = config.tcp.filter(|_| args.udp.is_none()) {
+        if addr.ip().is_unspecified() {
+            addr.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+        if config.tls.cert.is_some() && args.tls_ca.is_none() {
+            anyhow::bail!(
+                "the overlay's TCP listener on {} uses TLS, pass --tls-ca",
+                addr
+            );
+        }
+        return connect_tcp(args, addr);
+    }
+    let bind = config
+        .bind
+        .filter(|ip| !ip.is_unspecified())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    let addr = args
+        .udp
+        .or(config.udp.map(|port| SocketAddr::new(bind, port)))
+        .context(
+            "no overlay socket, TCP address or UDP port configured, pass --socket, --tcp or --udp",
+        )?;
+    let mut client = OverlayClient::udp(addr)?;
+    if let Some(path) = args
+        .secret_file
+        .as_ref()
+        .or(config.udp_secret_file.as_ref())
+    {
+        client = client.with_secret(load_secret(path)?);
+    }
+    Ok(client)
+}
+fn connect_tcp(args: &Args, addr: SocketAddr) -> Result<OverlayClient> {
+    let client = match &args.tls_ca {
+        Some(ca) => {
+            let mut tls = TlsOptions::new(ca);
+            tls.server_name = args.tls_server_name.clone();
+            if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+                tls = tls.identity(cert, key);
+            }
+            OverlayClient::tcp_tls(addr, &tls)
+        }
+        None => OverlayClient::tcp(addr),
+    };
+    client.with_context(|| format!("failed to connect to {}", addr))
+}
+/// Prints the data a command answered with, failing if it was rejected.
+fn report(response: Option<Response>) -> Result<()> {
+    let Some(response) = response else {
+        return Ok(());
+    };
+    if !response.ok {
+        anyhow::bail!("{}", response.message.unwrap_or_default());
+    }
+    if let Some(data) = response.data {
+        println!("{}", serde_json::to_string_pretty(&data)?);
+    }
+    Ok(())
+}
+/// Sends a command whose answer is the point, so it needs the Unix socket.
+fn query(client: &mut OverlayClient, command: impl Into<Command>) -> Result<()> {
+    let response = client
+        .send(command)?
+        .context("UDP carries no replies, use --socket")?;
+    report(Some(response))
+}
+fn run(args: Args) -> Result<()> {
+    let mut client = connect(&args)?;
+    match args.command {
+        Cmd::Send { file } => {
+            let mut text = String::new();
+            if file == "-" {
+                std::io::stdin().read_to_string(&mut text)?;
+            } else {
+                text = std::fs::read_to_string(&file)
+                    .with_context(|| format!("failed to read {}", file))?;
+            }
+            let commands = serde_json::Deserializer::from_str(&text).into_iter();
+            for command in commands {
+                let command: serde_json::Value = command.context("invalid JSON")?;
+                report(client.send_json(&command.to_string())?)?;
+            }
+            Ok(())
+        }
+        Cmd::Rect {
+            x1,
+            y1,
+            x2,
+            y2,
+            style,
+            layer,
+        } => {
+            let rect = RectangleParams::new(x1, y1, x2, y2)
+                .fill(style.fill)
+                .outline(style.outline_width, style.outline);
+            report(client.send(layer.command(rect))?)
+        }
+        Cmd::Circle {
+            x,
+            y,
+            radius,
+            style,
+            layer,
+        } => {
+            let circle = CircleParams::new(x, y, radius)
+                .fill(style.fill)
+                .outline(style.outline_width, style.outline);
+            report(client.send(layer.command(circle))?)
+        }
+        Cmd::Line {
+            x1,
+            y1,
+            x2,
+            y2,
+            width,
+            color,
+            layer,
+        } => {
+            let line = LineParams::new(x1, y1, x2, y2).width(width).color(color);
+            report(client.send(layer.command(line))?)
+        }
+        Cmd::Text {
+            text,
+            x,
+            y,
+            size,
+            color,
+            font,
+            layer,
+        } => {
+            let mut params = TextParams::new(x, y, text).size(size).color(color);
+            params.font = font;
+            report(client.send(layer.command(params))?)
+        }
+        Cmd::Clear { layer, .. } => {
+            let action = match layer {
+                Some(layer) => LayerAction::Clear { layer },
+                None => LayerAction::ClearAll,
+            };
+            report(client.send(action)?)
+        }
+        Cmd::Layers => query(&mut client, Query::ListLayers),
+        Cmd::Dump { layer } => query(&mut client, Query::DumpLayer { layer }),
+        Cmd::Surfaces => query(&mut client, Query::Surfaces),
+        Cmd::Stats => query(&mut client, Query::Stats),
+        Cmd::Snapshot { output } => query(&mut client, Query::Snapshot { output }),
+        Cmd::Events => {
+            query(&mut client, Subscription::Subscribe)?;
+            loop {
+                let event = client.next_event()?;
+                println!("{}", serde_json::to_string(&event)?);
+            }
+        }
+    }
+}
+fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(args) {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Transition {
+    #[serde(default)]
+    pub fade_in_ms: u64,
+    /// Fade applied once the layer's timeout expires; the layer is removed afterwards.
+    #[serde(default)]
+    pub fade_out_ms: u64,
+    /// When replacing a layer, numeric properties of its operations slide from the
+    /// previously shown values to the new ones over this duration.
+    #[serde(default)]
+    pub tween_ms: u64,
+    #[serde(default)]
+    pub easing: Easing,
+}
+impl Transition {
+    pub fn fade_in(&self) -> Duration {
+        Duration::from_millis(self.fade_in_ms)
+    }
+    pub fn fade_out(&self) -> Duration {
+        Duration::from_millis(self.fade_out_ms)
+    }
+    pub fn tween(&self) -> Duration {
+        Duration::from_millis(self.tween_ms)
+    }
+}
@@ -0,0 +1,167 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+/// How far a signed packet's timestamp may be from the listener's clock, either way.
+pub const REPLAY_WINDOW: Duration = Duration::from_secs(30);
+/// A UDP packet may wrap its command to authenticate it as
+/// `{"command": {...}, "timestamp": <ms>, "hmac": "<hex>"}`, where `timestamp` is the Unix time
+/// in milliseconds and `hmac` is the HMAC-SHA256 of the timestamp's decimal digits, a `.`, and
+/// the command's JSON text exactly as sent.
+#[derive(Serialize, Deserialize)]
+struct Envelope<'a> {
+    #[serde(borrow)]
+    command: &'a RawValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hmac: Option<String>,
+}
+/// Checks signed UDP packets, rejecting any that are stale or were already accepted.
+pub struct Verifier {
+    secret: Vec<u8>,
+    /// Tags of packets accepted within the window, with their timestamps.
+    seen: HashMap<Vec<u8>, u64>,
+}
+impl Verifier {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            seen: HashMap::new(),
+        }
+    }
+    fn verify<'a>(&mut self, envelope: Envelope<'a>, now: u64) -> Result<&'a str, &'static str> {
+        let command = envelope.command.get();
+        let hmac = envelope.hmac.ok_or("missing hmac")?;
+        let timestamp = envelope.timestamp.ok_or("missing timestamp")?;
+        let tag = hex::decode(hmac).map_err(|_| "hmac is not hex")?;
+        mac(&self.secret, timestamp, command)
+            .verify_slice(&tag)
+            .map_err(|_| "hmac mismatch")?;
+        let window = REPLAY_WINDOW.as_millis() as u64;
+        self.seen.retain(|_, seen| seen.abs_diff(now) <= window);
+        if timestamp.abs_diff(now) > window {
+            return Err("timestamp outside the replay window");
+        }
+        if self.seen.insert(tag, timestamp).is_some() {
+            return Err("replayed packet");
+        }
+        Ok(command)
+    }
+}
+/// Returns the command text of a UDP packet, checking its signature when a verifier is set.
+pub fn authenticate<'a>(
+    packet: &'a str,
+    verifier: Option<&mut Verifier>,
+) -> Result<&'a str, &'static str> {
+    let envelope = serde_json::from_str::<Envelope>(packet);
+    match (verifier, envelope) {
+        (Some(verifier), Ok(envelope)) => verifier.verify(envelope, unix_millis()),
+        (Some(_), Err(_)) => Err("missing hmac"),
+        (None, Ok(envelope)) => Ok(envelope.command.get()),
+        (None, Err(_)) => Ok(packet),
+    }
+}
+/// Reads a shared secret, ignoring surrounding whitespace such as a trailing newline.
+pub fn load_secret(path: &Path) -> io::Result<Vec<u8>> {
+    let error = |e: &dyn std::fmt::Display| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    };
+    let text = std::fs::read_to_string(path).map_err(|e| error(&e))?;
+    let secret = text.trim();
+    if secret.is_empty() {
+        return Err(error(&"the secret is empty"));
+    }
+    Ok(secret.as_bytes().to_vec())
+}
+/// Wraps command JSON in an envelope carrying the current time and its HMAC, for a listener
+/// that requires one.
+pub fn sign(command: &str, secret: &[u8]) -> serde_json::Result<String> {
+    sign_at(command, secret, unix_millis())
+}
+fn sign_at(command: &str, secret: &[u8], timestamp: u64) -> serde_json::Result<String> {
+    let hmac = hex::encode(mac(secret, timestamp, command).finalize().into_bytes());
+    let command = RawValue::from_string(command.to_string())?;
+    serde_json::to_string(&Envelope {
+        command: &command,
+        timestamp: Some(timestamp),
+        hmac: Some(hmac),
+    })
+}
+fn mac(secret: &[u8], timestamp: u64, command: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(command.as_bytes());
+    mac
+}
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Command, LayerAction};
+    fn command() -> String {
+        serde_json::to_string(&Command::from(LayerAction::ClearAll)).unwrap()
+    }
+    fn check(verifier: &mut Verifier, packet: &str, now: u64) -> Result<(), &'static str> {
+        let envelope = serde_json::from_str(packet).unwrap();
+        verifier.verify(envelope, now).map(|_| ())
+    }
+    #[test]
+    fn accepts_fresh_packets_once() {
+        let mut verifier = Verifier::new(*b"secret");
+        let packet = sign_at(&command(), b"secret", 1_000_000).unwrap();
+        assert_eq!(check(&mut verifier, &packet, 1_000_500), Ok(()));
+        assert_eq!(
+            check(&mut verifier, &packet, 1_000_600),
+            Err("replayed packet")
+        );
+        let next = sign_at(&command(), b"secret", 1_000_001).unwrap();
+        assert_eq!(check(&mut verifier, &next, 1_000_600), Ok(()));
+    }
+    #[test]
+    fn rejects_stale_and_forged_packets() {
+        let mut verifier = Verifier::new(*b"secret");
+        let packet = sign_at(&command(), b"secret", 1_000_000).unwrap();
+        assert_eq!(
+            check(&mut verifier, &packet, 1_031_000),
+            Err("timestamp outside the replay window")
+        );
+        let forged = packet.replace("1000000", "1031000");
+        assert_eq!(
+            check(&mut verifier, &forged, 1_031_000),
+            Err("hmac mismatch")
+        );
+        let unsigned = format!(r#"{{"command":{}}}"#, command());
+        assert_eq!(
+            check(&mut verifier, &unsigned, 1_000_000),
+            Err("missing hmac")
+        );
+        let command = command();
+        assert!(authenticate(&command, Some(&mut verifier)).is_err());
+        assert_eq!(authenticate(&command, None), Ok(command.as_str()));
+    }
+    #[test]
+    fn rejects_replays_after_the_window() {
+        let mut verifier = Verifier::new(*b"secret");
+        let packet = sign_at(&command(), b"secret", 1_000_000).unwrap();
+        assert_eq!(check(&mut verifier, &packet, 1_000_000), Ok(()));
+        // Forgotten by the seen list by now, but still refused as stale
+        let later = 1_000_000 + REPLAY_WINDOW.as_millis() as u64 + 1;
+        assert_eq!(
+            check(&mut verifier, &packet, later),
+            Err("timestamp outside the replay window")
+        );
+        assert!(verifier.seen.is_empty());
+    }
+}
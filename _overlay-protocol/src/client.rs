@@ -0,0 +1,317 @@
+use crate::auth::sign;
+use crate::command::Command;
+use crate::frame::{DEFAULT_MAX_FRAME_SIZE, Encoding, encode_frame, read_frame};
+use crate::response::{Event, Response};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+trait Connection: Read + Write + Send {}
+impl<T: Read + Write + Send> Connection for T {}
+enum Transport {
+    Udp(UdpSocket),
+    /// A Unix socket or TCP connection. Writes go straight to the inner stream.
+    Stream {
+        stream: BufReader<Box<dyn Connection>>,
+        framed: Option<Encoding>,
+    },
+}
+/// Sends commands to a running overlay. UDP is fire-and-forget; over the Unix socket and TCP
+/// every command is answered with a [`Response`] and subscribed events can be read back.
+pub struct OverlayClient {
+    transport: Transport,
+    secret: Option<Vec<u8>>,
+    /// Largest reply or event accepted, in bytes.
+    max_frame_size: usize,
+    /// Events that arrived while waiting for a response.
+    events: VecDeque<Event>,
+}
+impl OverlayClient {
+    pub fn udp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send to"))?;
+        let local = match addr {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        Ok(Self::new(Transport::Udp(socket)))
+    }
+    /// Connects to the Unix socket, sending newline-delimited JSON.
+    pub fn unix(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::connect_unix(path.as_ref(), None)
+    }
+    /// Connects to the Unix socket, sending length-prefixed frames in `encoding`.
+    pub fn unix_framed(path: impl AsRef<Path>, encoding: Encoding) -> io::Result<Self> {
+        Self::connect_unix(path.as_ref(), Some(encoding))
+    }
+    fn connect_unix(path: &Path, framed: Option<Encoding>) -> io::Result<Self> {
+        Self::connect_stream(Box::new(UnixStream::connect(path)?), framed)
+    }
+    /// Connects to the overlay's TCP listener, sending JSON frames.
+    pub fn tcp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Self::connect_stream(Box::new(stream), Some(Encoding::Json))
+    }
+    /// Connects to a TCP listener that requires TLS, sending JSON frames.
+    #[cfg(feature = "tls")]
+    pub fn tcp_tls(addr: impl ToSocketAddrs, tls: &crate::tls::TlsOptions) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let server_name = match &tls.server_name {
+            Some(name) => name.clone(),
+            None => stream.peer_addr()?.ip().to_string(),
+        };
+        let stream = tls.connect(&server_name, stream)?;
+        Self::connect_stream(Box::new(stream), Some(Encoding::Json))
+    }
+    fn connect_stream(
+        mut stream: Box<dyn Connection>,
+        framed: Option<Encoding>,
+    ) -> io::Result<Self> {
+        if let Some(encoding) = framed {
+            stream.write_all(&[encoding.magic()])?;
+            stream.flush()?;
+        }
+        Ok(Self::new(Transport::Stream {
+            stream: BufReader::new(stream),
+            framed,
+        }))
+    }
+    fn new(transport: Transport) -> Self {
+        Self {
+            transport,
+            secret: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            events: VecDeque::new(),
+        }
+    }
+    /// Signs UDP commands with the listener's shared secret. Commands sent over the Unix
+    /// socket or TCP are never signed.
+    pub fn with_secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+    /// Limits the size of replies and events, [`DEFAULT_MAX_FRAME_SIZE`] by default. Anything
+    /// larger fails with [`io::ErrorKind::InvalidData`].
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+    /// Sends a command, returning the overlay's response when the transport carries one.
+    pub fn send(&mut self, command: impl Into<Command>) -> io::Result<Option<Response>> {
+        let command = command.into();
+        if let Transport::Stream {
+            stream,
+            framed: Some(encoding),
+        } = &mut self.transport
+        {
+            let payload = encoding.encode(&command).map_err(io::Error::other)?;
+            let writer = stream.get_mut();
+            writer.write_all(&encode_frame(&payload))?;
+            writer.flush()?;
+            return self.read_response().map(Some);
+        }
+        let json = serde_json::to_string(&command)?;
+        self.send_json(&json)
+    }
+    /// Sends command JSON as it is, such as a command read from a file.
+    pub fn send_json(&mut self, json: &str) -> io::Result<Option<Response>> {
+        let json = json.trim();
+        match &mut self.transport {
+            Transport::Udp(socket) => {
+                let packet = match &self.secret {
+                    Some(secret) => sign(json, secret)?,
+                    None => json.to_string(),
+                };
+                socket.send(packet.as_bytes())?;
+                return Ok(None);
+            }
+            Transport::Stream {
+                stream,
+                framed: Some(encoding),
+            } => {
+                let command: serde_json::Value = serde_json::from_str(json)?;
+                let payload = encoding.encode(&command).map_err(io::Error::other)?;
+                stream.get_mut().write_all(&encode_frame(&payload))?;
+                stream.get_mut().flush()?;
+            }
+            Transport::Stream { stream, .. } => {
+                stream
+                    .get_mut()
+                    .write_all(format!("{}\n", json).as_bytes())?;
+                stream.get_mut().flush()?;
+            }
+        }
+        self.read_response().map(Some)
+    }
+    fn read_response(&mut self) -> io::Result<Response> {
+        loop {
+            match self.read_message()? {
+                Message::Response(response) => return Ok(response),
+                Message::Event(event) => self.events.push_back(event),
+            }
+        }
+    }
+    /// Waits for the next event after subscribing with [`Subscription::Subscribe`].
+    ///
+    /// [`Subscription::Subscribe`]: crate::command::Subscription::Subscribe
+    pub fn next_event(&mut self) -> io::Result<Event> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+        loop {
+            if let Message::Event(event) = self.read_message()? {
+                return Ok(event);
+            }
+        }
+    }
+    fn read_message(&mut self) -> io::Result<Message> {
+        let max_frame_size = self.max_frame_size;
+        let too_large = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("reply exceeds the maximum of {} bytes", max_frame_size),
+            )
+        };
+        let Transport::Stream {
+            stream: reader,
+            framed,
+        } = &mut self.transport
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "UDP carries no replies, use the Unix socket or TCP",
+            ));
+        };
+        let value: serde_json::Value = if let Some(encoding) = framed {
+            let frame = read_frame(reader, max_frame_size)?;
+            encoding.decode(&frame).map_err(io::Error::other)?
+        } else {
+            let mut line = String::new();
+            let limit = max_frame_size as u64 + 1;
+            if reader.by_ref().take(limit).read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if line.len() > max_frame_size && !line.ends_with('\n') {
+                return Err(too_large());
+            }
+            serde_json::from_str(&line)?
+        };
+        let message = if value.get("event").is_some() {
+            Message::Event(serde_json::from_value(value)?)
+        } else {
+            Message::Response(serde_json::from_value(value)?)
+        };
+        Ok(message)
+    }
+}
+/// A line or frame sent back over a stream connection.
+enum Message {
+    Response(Response),
+    Event(Event),
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::LayerAction;
+    use crate::response::ErrorKind;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::thread::{self, JoinHandle};
+    /// Listens on a fresh socket and runs `serve` on the first connection.
+    fn stub(
+        name: &str,
+        serve: impl FnOnce(UnixStream) -> io::Result<()> + Send + 'static,
+    ) -> (PathBuf, JoinHandle<io::Result<()>>) {
+        let path =
+            std::env::temp_dir().join(format!("overlay-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || serve(listener.accept()?.0));
+        (path, server)
+    }
+    fn cleared() -> serde_json::Value {
+        serde_json::to_value(Command::from(LayerAction::Clear { layer: 4 })).unwrap()
+    }
+    #[test]
+    fn round_trips_lines_over_unix_socket() {
+        let (path, server) = stub("lines", |stream| {
+            let mut reader = BufReader::new(&stream);
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let command: serde_json::Value = serde_json::from_str(&line)?;
+            assert_eq!(command, cleared());
+            let event = serde_json::to_string(&Event::LayerCleared { layer: 4 })?;
+            let reply = serde_json::to_string(&Response::ok())?;
+            (&stream).write_all(format!("{}\n{}\n", event, reply).as_bytes())
+        });
+        let mut client = OverlayClient::unix(&path).unwrap();
+        let response = client.send(LayerAction::Clear { layer: 4 }).unwrap();
+        assert!(response.unwrap().ok);
+        // The event that came first is kept for the subscriber
+        let event = client.next_event().unwrap();
+        assert!(matches!(event, Event::LayerCleared { layer: 4 }));
+        server.join().unwrap().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn round_trips_frames_over_unix_socket() {
+        let (path, server) = stub("frames", |mut stream| {
+            let mut magic = [0];
+            stream.read_exact(&mut magic)?;
+            let encoding = Encoding::from_magic(magic[0]).unwrap();
+            assert_eq!(encoding, Encoding::MessagePack);
+            let frame = read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE)?;
+            let command: serde_json::Value = encoding.decode(&frame).map_err(io::Error::other)?;
+            assert_eq!(command, cleared());
+            let reply = encoding
+                .encode(&Response::error(ErrorKind::UnknownLayer, "no layer 4"))
+                .map_err(io::Error::other)?;
+            stream.write_all(&encode_frame(&reply))
+        });
+        let mut client = OverlayClient::unix_framed(&path, Encoding::MessagePack).unwrap();
+        let response = client
+            .send(LayerAction::Clear { layer: 4 })
+            .unwrap()
+            .unwrap();
+        assert!(!response.ok);
+        assert!(response.error == Some(ErrorKind::UnknownLayer));
+        assert_eq!(response.message.as_deref(), Some("no layer 4"));
+        server.join().unwrap().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn rejects_replies_over_the_maximum() {
+        let long = Response::error(ErrorKind::Internal, "x".repeat(256));
+        let line = format!("{}\n", serde_json::to_string(&long).unwrap());
+        let (path, server) = stub("long-line", move |mut stream| {
+            BufReader::new(&stream).read_line(&mut String::new())?;
+            stream.write_all(line.as_bytes())
+        });
+        let mut client = OverlayClient::unix(&path).unwrap().with_max_frame_size(64);
+        let e = client.send(LayerAction::ClearAll).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        server.join().unwrap().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let frame = encode_frame(&serde_json::to_vec(&long).unwrap());
+        let (path, server) = stub("long-frame", move |mut stream| {
+            stream.read_exact(&mut [0])?;
+            read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE)?;
+            stream.write_all(&frame)
+        });
+        let mut client = OverlayClient::unix_framed(&path, Encoding::Json)
+            .unwrap()
+            .with_max_frame_size(64);
+        let e = client.send(LayerAction::ClearAll).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        server.join().unwrap().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
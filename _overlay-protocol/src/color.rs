@@ -0,0 +1,60 @@
+use crate::coord::Coord;
+use serde::{Deserialize, Serialize};
+/// A solid color or a gradient, accepted wherever shapes take a fill or stroke color.
+/// Colors are `0xAARRGGBB`, CSS colors or names from the overlay's palette.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum Brush {
+    Solid(String),
+    Gradient(Gradient),
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Gradient {
+    /// Blends the stops along the line from (`x1`, `y1`) to (`x2`, `y2`).
+    Linear {
+        x1: Coord,
+        y1: Coord,
+        x2: Coord,
+        y2: Coord,
+        stops: Vec<GradientStop>,
+    },
+    /// Blends the stops outwards from (`x`, `y`) to `radius`.
+    Radial {
+        x: Coord,
+        y: Coord,
+        radius: f32,
+        stops: Vec<GradientStop>,
+    },
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct GradientStop {
+    /// Position along the gradient, from 0 to 1.
+    pub offset: f32,
+    pub color: String,
+}
+impl GradientStop {
+    pub fn new(offset: f32, color: impl Into<String>) -> Self {
+        Self {
+            offset,
+            color: color.into(),
+        }
+    }
+}
+impl From<&str> for Brush {
+    fn from(color: &str) -> Self {
+        Brush::Solid(color.to_string())
+    }
+}
+impl From<String> for Brush {
+    fn from(color: String) -> Self {
+        Brush::Solid(color)
+    }
+}
+impl From<Gradient> for Brush {
+    fn from(gradient: Gradient) -> Self {
+        Brush::Gradient(gradient)
+    }
+}
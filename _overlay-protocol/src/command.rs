@@ -0,0 +1,351 @@
+use crate::animation::Transition;
+use crate::coord::Anchor;
+use crate::draw::DrawOperation;
+use crate::widget::Widget;
+use serde::{Deserialize, Serialize};
+/// Which outputs a layer is drawn on: `"all"`, `"focused"` or an output name such as `"DP-1"`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(from = "String", into = "String")]
+pub enum OutputTarget {
+    #[default]
+    All,
+    Focused,
+    Named(String),
+}
+impl From<String> for OutputTarget {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "all" => OutputTarget::All,
+            "focused" => OutputTarget::Focused,
+            _ => OutputTarget::Named(name),
+        }
+    }
+}
+impl From<&str> for OutputTarget {
+    fn from(name: &str) -> Self {
+        OutputTarget::from(name.to_string())
+    }
+}
+impl From<OutputTarget> for String {
+    fn from(target: OutputTarget) -> Self {
+        match target {
+            OutputTarget::All => "all".to_string(),
+            OutputTarget::Focused => "focused".to_string(),
+            OutputTarget::Named(name) => name,
+        }
+    }
+}
+impl OutputTarget {
+    pub fn matches(&self, output_name: Option<&str>) -> bool {
+        match self {
+            OutputTarget::All | OutputTarget::Focused => true,
+            OutputTarget::Named(name) => output_name == Some(name.as_str()),
+        }
+    }
+}
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for OutputTarget {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "OutputTarget".into()
+    }
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "\"all\", \"focused\" or an output name.",
+            "type": "string"
+        })
+    }
+}
+/// The original command shape: replaces a whole layer with new operations.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DrawCommand {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    pub operations: Vec<DrawOperation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition: Option<Transition>,
+    /// Defaults to the configured output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputTarget>,
+    /// Surface point that the operations' coordinates are relative to.
+    #[serde(default)]
+    pub anchor: Anchor,
+}
+impl DrawCommand {
+    /// An empty command for the default layer, without a timeout.
+    pub fn new() -> Self {
+        Self {
+            layer: None,
+            timeout_ms: None,
+            operations: Vec::new(),
+            transition: None,
+            output: None,
+            anchor: Anchor::default(),
+        }
+    }
+    pub fn layer(mut self, layer: i32) -> Self {
+        self.layer = Some(layer);
+        self
+    }
+    pub fn timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = Some(timeout_ms);
+        self
+    }
+    pub fn push(mut self, operation: impl Into<DrawOperation>) -> Self {
+        self.operations.push(operation.into());
+        self
+    }
+    pub fn transition(mut self, transition: Transition) -> Self {
+        self.transition = Some(transition);
+        self
+    }
+    pub fn output(mut self, output: impl Into<OutputTarget>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+}
+impl Default for DrawCommand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+/// Replaces a layer with a widget that the overlay expands into draw operations, styled by
+/// the theme.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct WidgetCommand {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    pub widget: Widget,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition: Option<Transition>,
+    /// Defaults to the configured output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputTarget>,
+    /// Defaults to the theme's corner for toasts and the top left for other widgets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<Anchor>,
+}
+impl WidgetCommand {
+    pub fn new(widget: Widget) -> Self {
+        Self {
+            layer: None,
+            timeout_ms: None,
+            widget,
+            transition: None,
+            output: None,
+            anchor: None,
+        }
+    }
+    pub fn layer(mut self, layer: i32) -> Self {
+        self.layer = Some(layer);
+        self
+    }
+    pub fn timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = Some(timeout_ms);
+        self
+    }
+    pub fn transition(mut self, transition: Transition) -> Self {
+        self.transition = Some(transition);
+        self
+    }
+    pub fn output(mut self, output: impl Into<OutputTarget>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = Some(anchor);
+        self
+    }
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum LayerAction {
+    Clear {
+        layer: i32,
+    },
+    ClearAll,
+    Append {
+        layer: i32,
+        operations: Vec<DrawOperation>,
+    },
+    SetTimeout {
+        layer: i32,
+        /// Milliseconds from now, or `null` to keep the layer until replaced.
+        ms: Option<u64>,
+    },
+    Hide {
+        layer: i32,
+    },
+    Show {
+        layer: i32,
+    },
+}
+/// Commands that inspect the overlay without changing what it shows. The answer is
+/// returned in the response's `data` field.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub enum Query {
+    ListLayers,
+    DumpLayer {
+        layer: i32,
+    },
+    Surfaces,
+    Stats,
+    /// Writes the rendered surface for `output` (or the first one) to the overlay's
+    /// `--snapshot` path.
+    Snapshot {
+        #[serde(default)]
+        output: Option<String>,
+    },
+}
+pub const QUERY_NAMES: &[&str] = &["ListLayers", "DumpLayer", "Surfaces", "Stats", "Snapshot"];
+/// Turns a Unix socket connection into an event stream, or back. Responses to further
+/// commands on the same connection are interleaved with the events.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Subscription {
+    Subscribe,
+    Unsubscribe,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum Command {
+    Action(LayerAction),
+    Query(Query),
+    Subscription(Subscription),
+    Replace(DrawCommand),
+    Widget(WidgetCommand),
+}
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Action(LayerAction::Clear { .. }) => "Clear",
+            Command::Action(LayerAction::ClearAll) => "ClearAll",
+            Command::Action(LayerAction::Append { .. }) => "Append",
+            Command::Action(LayerAction::SetTimeout { .. }) => "SetTimeout",
+            Command::Action(LayerAction::Hide { .. }) => "Hide",
+            Command::Action(LayerAction::Show { .. }) => "Show",
+            Command::Query(Query::ListLayers) => "ListLayers",
+            Command::Query(Query::DumpLayer { .. }) => "DumpLayer",
+            Command::Query(Query::Surfaces) => "Surfaces",
+            Command::Query(Query::Stats) => "Stats",
+            Command::Query(Query::Snapshot { .. }) => "Snapshot",
+            Command::Subscription(Subscription::Subscribe) => "Subscribe",
+            Command::Subscription(Subscription::Unsubscribe) => "Unsubscribe",
+            Command::Replace(_) => "Replace",
+            Command::Widget(command) => command.widget.name(),
+        }
+    }
+}
+impl From<LayerAction> for Command {
+    fn from(action: LayerAction) -> Self {
+        Command::Action(action)
+    }
+}
+impl From<Query> for Command {
+    fn from(query: Query) -> Self {
+        Command::Query(query)
+    }
+}
+impl From<Subscription> for Command {
+    fn from(subscription: Subscription) -> Self {
+        Command::Subscription(subscription)
+    }
+}
+impl From<DrawCommand> for Command {
+    fn from(command: DrawCommand) -> Self {
+        Command::Replace(command)
+    }
+}
+impl From<WidgetCommand> for Command {
+    fn from(command: WidgetCommand) -> Self {
+        Command::Widget(command)
+    }
+}
+/// Fields of [`DrawCommand`], none of which is a layer action or query name.
+const DRAW_FIELDS: &[&str] = &[
+    "layer",
+    "timeout_ms",
+    "operations",
+    "transition",
+    "output",
+    "anchor",
+];
+/// Parses a JSON command. The untagged `Command` enum only reports that no variant matched,
+/// so on a data error the input is re-parsed as the shape the client most likely meant, giving
+/// an error that names and locates the offending field.
+pub fn parse_command(json: &str) -> serde_json::Result<Command> {
+    serde_json::from_str::<Command>(json).or_else(|e| {
+        if e.is_syntax() || e.is_eof() {
+            return Err(e);
+        }
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let tag = match &value {
+            serde_json::Value::String(tag) => Some(tag.as_str()),
+            serde_json::Value::Object(map) if map.len() == 1 => {
+                map.keys().next().map(String::as_str)
+            }
+            _ => None,
+        };
+        let has_draw_fields = value
+            .as_object()
+            .is_some_and(|map| map.keys().any(|key| DRAW_FIELDS.contains(&key.as_str())));
+        if value.get("widget").is_some() {
+            serde_json::from_str::<WidgetCommand>(json).map(Command::Widget)
+        } else if has_draw_fields {
+            serde_json::from_str::<DrawCommand>(json).map(Command::Replace)
+        } else if tag.is_some_and(|tag| QUERY_NAMES.contains(&tag)) {
+            serde_json::from_str::<Query>(json).map(Command::Query)
+        } else {
+            serde_json::from_str::<LayerAction>(json).map(Command::Action)
+        }
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn error(json: &str) -> serde_json::Error {
+        parse_command(json)
+            .err()
+            .expect("command should be rejected")
+    }
+    #[test]
+    fn reports_draw_command_errors() {
+        let e = error(r#"{"layer":1}"#);
+        assert!(
+            e.to_string().starts_with("missing field `operations`"),
+            "{}",
+            e
+        );
+        assert_eq!((e.line(), e.column()), (1, 11));
+        let e = error("{\"layer\": 1,\n \"operations\": [{\"Pixel\": {\"x\": 1}}]}");
+        assert!(e.to_string().starts_with("missing field `y`"), "{}", e);
+        assert_eq!(e.line(), 2);
+    }
+    #[test]
+    fn reports_other_command_errors() {
+        let e = error(r#"{"layer":1,"widget":{"Badge":{}}}"#);
+        assert!(e.to_string().starts_with("missing field"), "{}", e);
+        let e = error(r#"{"Clear":{}}"#);
+        assert!(e.to_string().starts_with("missing field `layer`"), "{}", e);
+        let e = error(r#"{"DumpLayer":{}}"#);
+        assert!(e.to_string().starts_with("missing field `layer`"), "{}", e);
+        let e = error(r#""Bogus""#);
+        assert!(
+            e.to_string().starts_with("unknown variant `Bogus`"),
+            "{}",
+            e
+        );
+    }
+}
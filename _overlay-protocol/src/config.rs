@@ -0,0 +1,126 @@
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+/// The listener settings in the overlay's `config.toml`, read by the overlay to start them and
+/// by clients to find them.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Listeners {
+    pub udp: Option<u16>,
+    /// Address the UDP listener binds to, loopback by default.
+    pub bind: Option<IpAddr>,
+    /// File holding the shared secret that UDP commands must be signed with, relative to
+    /// the config file's directory.
+    pub udp_secret_file: Option<PathBuf>,
+    pub socket: Option<String>,
+    /// Address for a TCP listener, off by default.
+    pub tcp: Option<SocketAddr>,
+    pub tls: TlsFiles,
+}
+/// PEM files securing the TCP listener, relative to the config file's directory.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsFiles {
+    /// Server certificate chain; TLS is enabled when it's set.
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// CA that client certificates must be issued by. Clients without one are rejected.
+    pub client_ca: Option<PathBuf>,
+}
+impl TlsFiles {
+    /// Takes each file that isn't set here from `fallback`.
+    pub fn or(self, fallback: TlsFiles) -> TlsFiles {
+        TlsFiles {
+            cert: self.cert.or(fallback.cert),
+            key: self.key.or(fallback.key),
+            client_ca: self.client_ca.or(fallback.client_ca),
+        }
+    }
+}
+impl Listeners {
+    /// Reads the listeners from the config at `path`, or at the default path if none is
+    /// given, ignoring the rest of the file.
+    pub fn load(path: Option<&Path>) -> io::Result<Self> {
+        let (mut listeners, path) = load::<Listeners>(path)?;
+        if let Some(dir) = path.as_deref().and_then(Path::parent) {
+            listeners.resolve_paths(dir);
+        }
+        Ok(listeners)
+    }
+    /// Makes the files relative to `dir`, the directory of the config they were read from.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        let tls = &mut self.tls;
+        for file in [
+            &mut self.udp_secret_file,
+            &mut tls.cert,
+            &mut tls.key,
+            &mut tls.client_ca,
+        ] {
+            *file = file.take().map(|file| dir.join(file));
+        }
+    }
+}
+/// `$XDG_CONFIG_HOME/overlay/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("overlay").join("config.toml"))
+}
+/// Reads the config at `path`, or at the default path if none is given, returning it with
+/// the path it was read from. Only a missing default file is allowed, and gives the default.
+pub fn load<T: DeserializeOwned + Default>(
+    path: Option<&Path>,
+) -> io::Result<(T, Option<PathBuf>)> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok((T::default(), None)),
+        },
+    };
+    let error = |e: &dyn std::fmt::Display| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    };
+    let text = std::fs::read_to_string(&path).map_err(|e| error(&e))?;
+    let config = toml::from_str(&text).map_err(|e| error(&format!("invalid config: {}", e)))?;
+    Ok((config, Some(path)))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn resolves_files_next_to_the_config() {
+        let path =
+            std::env::temp_dir().join(format!("overlay-{}-listeners.toml", std::process::id()));
+        // Settings that aren't about listeners are left to the overlay
+        let text = r#"
+            udp = 9000
+            udp_secret_file = "secret"
+            layer = "top"
+            [tls]
+            cert = "/etc/cert.pem"
+            key = "key.pem"
+        "#;
+        std::fs::write(&path, text).unwrap();
+        let listeners = Listeners::load(Some(&path));
+        std::fs::remove_file(&path).unwrap();
+        let listeners = listeners.unwrap();
+        let dir = std::env::temp_dir();
+        assert_eq!(listeners.udp, Some(9000));
+        assert_eq!(listeners.udp_secret_file, Some(dir.join("secret")));
+        assert_eq!(listeners.tls.cert, Some(PathBuf::from("/etc/cert.pem")));
+        assert_eq!(listeners.tls.key, Some(dir.join("key.pem")));
+        assert_eq!(listeners.tls.client_ca, None);
+    }
+    #[test]
+    fn only_the_default_config_may_be_missing() {
+        let path = std::env::temp_dir()
+            .join("overlay-missing")
+            .join("config.toml");
+        let e = Listeners::load(Some(&path)).err().unwrap();
+        assert!(e.to_string().starts_with(&path.display().to_string()));
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize, Serializer};
+/// A coordinate along one axis: a percentage of the surface size plus a pixel offset.
+/// Accepts plain numbers (`12`) or strings such as `"50%"`, `"100%-10"` or `"-24"`.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(try_from = "CoordRepr")]
+pub struct Coord {
+    pub percent: f32,
+    pub pixels: f32,
+}
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CoordRepr {
+    Number(f32),
+    Text(String),
+}
+impl TryFrom<CoordRepr> for Coord {
+    type Error = String;
+    fn try_from(repr: CoordRepr) -> Result<Self, Self::Error> {
+        match repr {
+            CoordRepr::Number(pixels) if pixels.is_finite() => Ok(Coord::pixels(pixels)),
+            CoordRepr::Number(pixels) => Err(format!("invalid coordinate {}", pixels)),
+            CoordRepr::Text(text) => text.parse(),
+        }
+    }
+}
+impl std::str::FromStr for Coord {
+    type Err = String;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let invalid = || format!("invalid coordinate '{}'", text);
+        if compact.is_empty() {
+            return Err(invalid());
+        }
+        let (percent, pixels): (f32, _) = match compact.split_once('%') {
+            Some((percent, pixels)) => (percent.parse().map_err(|_| invalid())?, pixels),
+            None => (0.0, compact.as_str()),
+        };
+        let pixels: f32 = match pixels.strip_suffix("px").unwrap_or(pixels) {
+            "" => 0.0,
+            pixels => pixels.parse().map_err(|_| invalid())?,
+        };
+        // `parse` accepts "NaN" and "inf", which can't be drawn
+        if !percent.is_finite() || !pixels.is_finite() {
+            return Err(invalid());
+        }
+        Ok(Coord { percent, pixels })
+    }
+}
+impl std::fmt::Display for Coord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.percent, self.pixels) {
+            (percent, 0.0) => write!(f, "{}%", percent),
+            (percent, pixels) => write!(f, "{}%{:+}", percent, pixels),
+        }
+    }
+}
+impl Serialize for Coord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.percent == 0.0 {
+            serializer.serialize_f32(self.pixels)
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+impl Coord {
+    pub fn pixels(pixels: f32) -> Self {
+        Coord {
+            percent: 0.0,
+            pixels,
+        }
+    }
+    /// A percentage of the surface size plus a pixel offset, as in `"100%-10"`.
+    pub fn new(percent: f32, pixels: f32) -> Self {
+        Coord { percent, pixels }
+    }
+    pub fn percent(percent: f32) -> Self {
+        Coord {
+            percent,
+            pixels: 0.0,
+        }
+    }
+    /// Resolves against an axis of `size` pixels whose anchor point is at `origin`.
+    pub fn resolve(self, origin: f32, size: f32) -> f32 {
+        origin + self.percent / 100.0 * size + self.pixels
+    }
+    pub fn lerp(self, to: Coord, t: f32) -> Coord {
+        Coord {
+            percent: self.percent + (to.percent - self.percent) * t,
+            pixels: self.pixels + (to.pixels - self.pixels) * t,
+        }
+    }
+}
+impl From<f32> for Coord {
+    fn from(pixels: f32) -> Self {
+        Coord::pixels(pixels)
+    }
+}
+impl From<i32> for Coord {
+    fn from(pixels: i32) -> Self {
+        Coord::pixels(pixels as f32)
+    }
+}
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Coord {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Coord".into()
+    }
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "Pixels as a number, or a string such as \"50%\" or \"100%-10\".",
+            "type": ["number", "string"]
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::parse_command;
+    fn parse(text: &str) -> Result<(f32, f32), String> {
+        text.parse::<Coord>().map(|c| (c.percent, c.pixels))
+    }
+    #[test]
+    fn parses_percentages_and_offsets() {
+        assert_eq!(parse("50%"), Ok((50.0, 0.0)));
+        assert_eq!(parse("100%-10"), Ok((100.0, -10.0)));
+        assert_eq!(parse("100% - 10px"), Ok((100.0, -10.0)));
+        assert_eq!(parse("-24"), Ok((0.0, -24.0)));
+        assert_eq!(parse("12.5"), Ok((0.0, 12.5)));
+        let coord: Coord = serde_json::from_str("12").unwrap();
+        assert!(coord == Coord::pixels(12.0));
+        let coord: Coord = serde_json::from_str(r#""25%+4""#).unwrap();
+        assert!(coord == Coord::new(25.0, 4.0));
+    }
+    #[test]
+    fn rejects_bad_coordinates() {
+        for text in [
+            "", "%", "abc", "50%%", "10px5", "NaN", "inf", "-inf", "NaN%", "1e39",
+        ] {
+            assert_eq!(
+                parse(text),
+                Err(format!("invalid coordinate '{}'", text)),
+                "{}",
+                text
+            );
+        }
+        assert!(serde_json::from_str::<Coord>("1e39").is_err());
+        let command = r#"{"operations":[{"Pixel":{"x":"NaN","y":"inf","color":"red"}}]}"#;
+        assert!(parse_command(command).is_err());
+    }
+}
+/// The point of the surface that coordinates are measured from.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+impl Anchor {
+    /// Position of the anchor as fractions of the surface width and height.
+    pub fn fractions(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use crate::color::Brush;
+use crate::coord::Coord;
+use serde::{Deserialize, Serialize};
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum LineSide {
+    Left,
+    Right,
+    Center,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PixelParams {
+    pub x: Coord,
+    pub y: Coord,
+    pub color: Brush,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LineParams {
+    pub x1: Coord,
+    pub y1: Coord,
+    pub x2: Coord,
+    pub y2: Coord,
+    pub width: f32,
+    pub side: LineSide,
+    pub color: Brush,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CircleParams {
+    pub x: Coord,
+    pub y: Coord,
+    pub radius: f32,
+    pub fill_color: Brush,
+    pub outline_width: f32,
+    pub outline_color: Brush,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RectangleParams {
+    pub x1: Coord,
+    pub y1: Coord,
+    pub x2: Coord,
+    pub y2: Coord,
+    pub fill_color: Brush,
+    pub outline_width: f32,
+    pub outline_color: Brush,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TextParams {
+    pub x: Coord,
+    pub y: Coord,
+    pub text: String,
+    /// Font family name, or a path to a font file in the overlay's `fonts_dir` when it
+    /// contains a `/`.
+    pub font: Option<String>,
+    pub size: f32,
+    /// Gradients are placed like shape coordinates and span the whole text.
+    pub color: Brush,
+    #[serde(default)]
+    pub align: TextAlign,
+    /// Wrap lines longer than this width.
+    pub max_width: Option<Coord>,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum PathFillRule {
+    #[default]
+    Winding,
+    EvenOdd,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum PathLineCap {
+    #[default]
+    Butt,
+    Round,
+    Square,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum PathLineJoin {
+    #[default]
+    Miter,
+    Round,
+    Bevel,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PathParams {
+    /// SVG path data, e.g. `M 0 0 L 10 10 Z`.
+    pub d: String,
+    pub fill_color: Option<Brush>,
+    #[serde(default)]
+    pub fill_rule: PathFillRule,
+    pub stroke_color: Option<Brush>,
+    #[serde(default)]
+    pub stroke_width: f32,
+    #[serde(default)]
+    pub line_cap: PathLineCap,
+    #[serde(default)]
+    pub line_join: PathLineJoin,
+    /// Alternating dash and gap lengths.
+    pub dash: Option<Vec<f32>>,
+    #[serde(default)]
+    pub dash_offset: f32,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ImageFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+    Bicubic,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ImageParams {
+    /// PNG or QOI file to load from the overlay's `images_dir`.
+    pub path: Option<String>,
+    /// Base64-encoded PNG or QOI payload, used when `path` is not set.
+    pub data: Option<String>,
+    pub x: Coord,
+    pub y: Coord,
+    /// Destination size; defaults to the image's natural size.
+    pub width: Option<Coord>,
+    pub height: Option<Coord>,
+    #[serde(default)]
+    pub filter: ImageFilter,
+    pub opacity: Option<f32>,
+}
+/// An invisible rectangle that accepts pointer input and reports clicks to subscribers.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ClickableParams {
+    pub id: String,
+    pub x1: Coord,
+    pub y1: Coord,
+    pub x2: Coord,
+    pub y2: Coord,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum DrawOperation {
+    Pixel(PixelParams),
+    Line(LineParams),
+    Circle(CircleParams),
+    Rectangle(RectangleParams),
+    Text(TextParams),
+    Path(PathParams),
+    Image(ImageParams),
+    Clickable(ClickableParams),
+}
+const TRANSPARENT: &str = "0x00000000";
+const WHITE: &str = "0xFFFFFFFF";
+impl PixelParams {
+    pub fn new(x: impl Into<Coord>, y: impl Into<Coord>, color: impl Into<Brush>) -> Self {
+        Self {
+            x: x.into(),
+            y: y.into(),
+            color: color.into(),
+        }
+    }
+}
+impl LineParams {
+    /// A white line one pixel wide, centred on the points.
+    pub fn new(
+        x1: impl Into<Coord>,
+        y1: impl Into<Coord>,
+        x2: impl Into<Coord>,
+        y2: impl Into<Coord>,
+    ) -> Self {
+        Self {
+            x1: x1.into(),
+            y1: y1.into(),
+            x2: x2.into(),
+            y2: y2.into(),
+            width: 1.0,
+            side: LineSide::Center,
+            color: WHITE.into(),
+        }
+    }
+    pub fn width(mut self, width: f32) -> Self {
+        self.width = width;
+        self
+    }
+    pub fn side(mut self, side: LineSide) -> Self {
+        self.side = side;
+        self
+    }
+    pub fn color(mut self, color: impl Into<Brush>) -> Self {
+        self.color = color.into();
+        self
+    }
+}
+impl CircleParams {
+    /// A circle with neither fill nor outline.
+    pub fn new(x: impl Into<Coord>, y: impl Into<Coord>, radius: f32) -> Self {
+        Self {
+            x: x.into(),
+            y: y.into(),
+            radius,
+            fill_color: TRANSPARENT.into(),
+            outline_width: 0.0,
+            outline_color: TRANSPARENT.into(),
+        }
+    }
+    pub fn fill(mut self, color: impl Into<Brush>) -> Self {
+        self.fill_color = color.into();
+        self
+    }
+    pub fn outline(mut self, width: f32, color: impl Into<Brush>) -> Self {
+        self.outline_width = width;
+        self.outline_color = color.into();
+        self
+    }
+}
+impl RectangleParams {
+    /// A rectangle with neither fill nor outline.
+    pub fn new(
+        x1: impl Into<Coord>,
+        y1: impl Into<Coord>,
+        x2: impl Into<Coord>,
+        y2: impl Into<Coord>,
+    ) -> Self {
+        Self {
+            x1: x1.into(),
+            y1: y1.into(),
+            x2: x2.into(),
+            y2: y2.into(),
+            fill_color: TRANSPARENT.into(),
+            outline_width: 0.0,
+            outline_color: TRANSPARENT.into(),
+        }
+    }
+    pub fn fill(mut self, color: impl Into<Brush>) -> Self {
+        self.fill_color = color.into();
+        self
+    }
+    pub fn outline(mut self, width: f32, color: impl Into<Brush>) -> Self {
+        self.outline_width = width;
+        self.outline_color = color.into();
+        self
+    }
+}
+impl TextParams {
+    /// White 16 pixel text in the default font.
+    pub fn new(x: impl Into<Coord>, y: impl Into<Coord>, text: impl Into<String>) -> Self {
+        Self {
+            x: x.into(),
+            y: y.into(),
+            text: text.into(),
+            font: None,
+            size: 16.0,
+            color: WHITE.into(),
+            align: TextAlign::Left,
+            max_width: None,
+        }
+    }
+    pub fn font(mut self, font: impl Into<String>) -> Self {
+        self.font = Some(font.into());
+        self
+    }
+    pub fn size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn color(mut self, color: impl Into<Brush>) -> Self {
+        self.color = color.into();
+        self
+    }
+    pub fn align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+    pub fn max_width(mut self, max_width: impl Into<Coord>) -> Self {
+        self.max_width = Some(max_width.into());
+        self
+    }
+}
+impl PathParams {
+    /// A path with neither fill nor stroke.
+    pub fn new(d: impl Into<String>) -> Self {
+        Self {
+            d: d.into(),
+            fill_color: None,
+            fill_rule: PathFillRule::default(),
+            stroke_color: None,
+            stroke_width: 0.0,
+            line_cap: PathLineCap::default(),
+            line_join: PathLineJoin::default(),
+            dash: None,
+            dash_offset: 0.0,
+        }
+    }
+    pub fn fill(mut self, color: impl Into<Brush>) -> Self {
+        self.fill_color = Some(color.into());
+        self
+    }
+    pub fn stroke(mut self, width: f32, color: impl Into<Brush>) -> Self {
+        self.stroke_width = width;
+        self.stroke_color = Some(color.into());
+        self
+    }
+    pub fn dash(mut self, dash: Vec<f32>, offset: f32) -> Self {
+        self.dash = Some(dash);
+        self.dash_offset = offset;
+        self
+    }
+}
+impl ImageParams {
+    /// An image file drawn at its natural size.
+    pub fn file(path: impl Into<String>, x: impl Into<Coord>, y: impl Into<Coord>) -> Self {
+        Self {
+            path: Some(path.into()),
+            data: None,
+            x: x.into(),
+            y: y.into(),
+            width: None,
+            height: None,
+            filter: ImageFilter::default(),
+            opacity: None,
+        }
+    }
+    pub fn size(mut self, width: impl Into<Coord>, height: impl Into<Coord>) -> Self {
+        self.width = Some(width.into());
+        self.height = Some(height.into());
+        self
+    }
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = Some(opacity);
+        self
+    }
+}
+impl ClickableParams {
+    pub fn new(
+        id: impl Into<String>,
+        x1: impl Into<Coord>,
+        y1: impl Into<Coord>,
+        x2: impl Into<Coord>,
+        y2: impl Into<Coord>,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            x1: x1.into(),
+            y1: y1.into(),
+            x2: x2.into(),
+            y2: y2.into(),
+        }
+    }
+}
+impl From<PixelParams> for DrawOperation {
+    fn from(params: PixelParams) -> Self {
+        DrawOperation::Pixel(params)
+    }
+}
+impl From<LineParams> for DrawOperation {
+    fn from(params: LineParams) -> Self {
+        DrawOperation::Line(params)
+    }
+}
+impl From<CircleParams> for DrawOperation {
+    fn from(params: CircleParams) -> Self {
+        DrawOperation::Circle(params)
+    }
+}
+impl From<RectangleParams> for DrawOperation {
+    fn from(params: RectangleParams) -> Self {
+        DrawOperation::Rectangle(params)
+    }
+}
+impl From<TextParams> for DrawOperation {
+    fn from(params: TextParams) -> Self {
+        DrawOperation::Text(params)
+    }
+}
+impl From<PathParams> for DrawOperation {
+    fn from(params: PathParams) -> Self {
+        DrawOperation::Path(params)
+    }
+}
+impl From<ImageParams> for DrawOperation {
+    fn from(params: ImageParams) -> Self {
+        DrawOperation::Image(params)
+    }
+}
+impl From<ClickableParams> for DrawOperation {
+    fn from(params: ClickableParams) -> Self {
+        DrawOperation::Clickable(params)
+    }
+}
@@ -0,0 +1,191 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io::{self, Read};
+/// Sent as the first byte of a stream connection to switch it from newline-delimited JSON
+/// to frames: a 4-byte big-endian length followed by that many bytes of JSON. Responses and
+/// events on the connection are framed the same way.
+pub const FRAMED_JSON: u8 = 0x01;
+/// Like `FRAMED_JSON`, with MessagePack frames.
+pub const FRAMED_MSGPACK: u8 = 0x02;
+/// Like `FRAMED_JSON`, with CBOR frames.
+pub const FRAMED_CBOR: u8 = 0x03;
+/// Largest command the overlay accepts on a stream connection unless configured otherwise.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+/// How the frames of a connection are encoded. The binary encodings use the same field and
+/// variant names as JSON.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+    Cbor,
+}
+impl Encoding {
+    /// The encoding selected by the first byte of a connection, if it selects one.
+    pub fn from_magic(byte: u8) -> Option<Self> {
+        match byte {
+            FRAMED_JSON => Some(Encoding::Json),
+            FRAMED_MSGPACK => Some(Encoding::MessagePack),
+            FRAMED_CBOR => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+    pub fn magic(self) -> u8 {
+        match self {
+            Encoding::Json => FRAMED_JSON,
+            Encoding::MessagePack => FRAMED_MSGPACK,
+            Encoding::Cbor => FRAMED_CBOR,
+        }
+    }
+    pub fn encode(self, value: &impl Serialize) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            // Structs are written as maps so that fields can be omitted or reordered
+            Encoding::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Encoding::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+        }
+    }
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Encoding::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Encoding::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Encoding::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+/// Prefixes `payload` with its length.
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+/// Reads one length-prefixed frame, refusing frames larger than `max_frame_size` before
+/// allocating for them.
+pub fn read_frame(reader: &mut impl Read, max_frame_size: usize) -> io::Result<Vec<u8>> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > max_frame_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame exceeds the maximum of {} bytes", max_frame_size),
+        ));
+    }
+    let mut frame = vec![0; length];
+    reader.read_exact(&mut frame)?;
+    Ok(frame)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{Gradient, GradientStop};
+    use crate::command::{Command, DrawCommand, LayerAction, Query, Subscription, WidgetCommand};
+    use crate::coord::Coord;
+    use crate::draw::{PathParams, PixelParams, TextParams};
+    use crate::widget::{BadgeParams, Widget};
+    const ENCODINGS: [Encoding; 3] = [Encoding::Json, Encoding::MessagePack, Encoding::Cbor];
+    fn commands() -> Vec<Command> {
+        let gradient = Gradient::Linear {
+            x1: Coord::pixels(0.0),
+            y1: Coord::pixels(0.0),
+            x2: Coord::percent(100.0),
+            y2: Coord::pixels(0.0),
+            stops: vec![
+                GradientStop::new(0.0, "red"),
+                GradientStop::new(1.0, "#00f8"),
+            ],
+        };
+        let draw = DrawCommand::new()
+            .layer(3)
+            .push(PixelParams::new(Coord::new(50.0, -2.0), 4, "0xFFFF0000"))
+            .push(TextParams::new(8, 8, "Volume").color(gradient))
+            .push(PathParams::new("M 0 0 L 10 10").stroke(2.0, "white"));
+        let badge = Widget::Badge(BadgeParams {
+            x: Coord::percent(100.0),
+            y: Coord::pixels(0.0),
+            text: "3".to_string(),
+            color: None,
+        });
+        vec![
+            LayerAction::ClearAll.into(),
+            LayerAction::SetTimeout { layer: 2, ms: None }.into(),
+            Query::ListLayers.into(),
+            Query::DumpLayer { layer: 1 }.into(),
+            Subscription::Subscribe.into(),
+            draw.into(),
+            WidgetCommand::new(badge).into(),
+        ]
+    }
+    #[test]
+    fn magic_bytes_select_encodings() {
+        for encoding in ENCODINGS {
+            assert_eq!(Encoding::from_magic(encoding.magic()), Some(encoding));
+        }
+        // Anything else starts a newline-delimited JSON connection
+        for byte in [0x00, 0x04, b'{', b'"', b' ', 0xFF] {
+            assert_eq!(Encoding::from_magic(byte), None);
+        }
+    }
+    #[test]
+    fn commands_round_trip_in_every_encoding() {
+        for command in commands() {
+            let json = serde_json::to_value(&command).unwrap();
+            for encoding in ENCODINGS {
+                let bytes = encoding.encode(&command).unwrap();
+                let decoded: Command = encoding
+                    .decode(&bytes)
+                    .unwrap_or_else(|e| panic!("{:?} {}: {}", encoding, json, e));
+                assert_eq!(
+                    serde_json::to_value(&decoded).unwrap(),
+                    json,
+                    "{:?}",
+                    encoding
+                );
+            }
+        }
+    }
+    #[test]
+    fn rejects_undecodable_frames() {
+        for encoding in ENCODINGS {
+            assert!(encoding.decode::<Command>(&[0xC1, 0xFF]).is_err());
+        }
+    }
+    #[test]
+    fn frames_are_length_prefixed() {
+        let frame = encode_frame(b"{}");
+        assert_eq!(frame, [0, 0, 0, 2, b'{', b'}']);
+        let stream = [
+            encode_frame(b"first"),
+            encode_frame(b""),
+            encode_frame(b"third"),
+        ]
+        .concat();
+        let mut reader = stream.as_slice();
+        assert_eq!(read_frame(&mut reader, 16).unwrap(), b"first");
+        assert_eq!(read_frame(&mut reader, 16).unwrap(), b"");
+        assert_eq!(read_frame(&mut reader, 16).unwrap(), b"third");
+        assert!(reader.is_empty());
+    }
+    #[test]
+    fn rejects_frames_over_the_maximum() {
+        let frame = encode_frame(&[0; 17]);
+        assert_eq!(read_frame(&mut frame.as_slice(), 17).unwrap().len(), 17);
+        let e = read_frame(&mut frame.as_slice(), 16).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        // The length alone is enough to refuse a frame
+        let e = read_frame(&mut &u32::MAX.to_be_bytes()[..], 16).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+    #[test]
+    fn rejects_truncated_frames() {
+        let frame = encode_frame(b"truncated");
+        for len in [0, 3, 4, frame.len() - 1] {
+            let e = read_frame(&mut &frame[..len], 64).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof, "{} bytes", len);
+        }
+    }
+}
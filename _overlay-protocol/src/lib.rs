@@ -0,0 +1,20 @@
+pub mod animation;
+pub mod auth;
+pub mod client;
+pub mod color;
+pub mod command;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod coord;
+pub mod draw;
+pub mod frame;
+pub mod response;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod widget;
+pub use client::OverlayClient;
+pub use command::Command;
+pub use draw::DrawOperation;
+pub use response::{Event, Response};
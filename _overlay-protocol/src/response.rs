@@ -0,0 +1,179 @@
+use crate::command::OutputTarget;
+use crate::coord::Anchor;
+use crate::draw::DrawOperation;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LayerSummary {
+    pub layer: i32,
+    pub operations: usize,
+    pub visible: bool,
+    /// Time left before the layer expires, `None` if it has no timeout.
+    pub remaining_ms: Option<u64>,
+    pub output: OutputTarget,
+    pub anchor: Anchor,
+}
+#[derive(Serialize)]
+pub struct LayerDump<'a> {
+    pub layer: i32,
+    pub operations: &'a [DrawOperation],
+}
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SurfaceInfo {
+    pub output: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    pub configured: bool,
+}
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RenderStats {
+    /// Main loop iterations since startup.
+    pub ticks: u64,
+    /// Surface frames drawn and committed since startup.
+    pub frames_rendered: u64,
+    pub last_render_ms: Option<f64>,
+    pub since_last_render_ms: Option<u64>,
+}
+impl RenderStats {
+    /// `last_render` is when the last frame was drawn and how long drawing it took.
+    pub fn new(ticks: u64, frames_rendered: u64, last_render: Option<(Instant, Duration)>) -> Self {
+        Self {
+            ticks,
+            frames_rendered,
+            last_render_ms: last_render.map(|(_, took)| took.as_secs_f64() * 1000.0),
+            since_last_render_ms: last_render.map(|(at, _)| at.elapsed().as_millis() as u64),
+        }
+    }
+}
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SnapshotInfo {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+}
+/// Sent to subscribed clients as one JSON object per line, tagged by `event`.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A pointer button was pressed inside a clickable region.
+    Click {
+        id: String,
+        layer: i32,
+        /// Linux input event code, e.g. 272 for the left button.
+        button: u32,
+        x: f64,
+        y: f64,
+        output: Option<String>,
+    },
+    Enter {
+        id: String,
+        layer: i32,
+        output: Option<String>,
+    },
+    Leave {
+        id: String,
+        layer: i32,
+        output: Option<String>,
+    },
+    /// A layer reached its timeout and was removed.
+    LayerExpired {
+        layer: i32,
+    },
+    /// A draw command replaced the contents of a layer, or `Append` added to them.
+    LayerReplaced {
+        layer: i32,
+    },
+    /// `Clear` or `ClearAll` removed a layer.
+    LayerCleared {
+        layer: i32,
+    },
+    OutputAdded {
+        output: Option<String>,
+    },
+    OutputRemoved {
+        output: Option<String>,
+    },
+    /// The compositor sized the surface on an output, in logical pixels.
+    SurfaceConfigured {
+        output: Option<String>,
+        width: u32,
+        height: u32,
+    },
+    SurfaceClosed {
+        output: Option<String>,
+    },
+}
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Parse,
+    UnknownLayer,
+    Snapshot,
+    InvalidColor,
+    /// A command was longer than the overlay's maximum frame size. The connection is closed.
+    FrameTooLarge,
+    Internal,
+}
+/// Reply written back to stream clients after each command.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+impl Response {
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            message: None,
+            line: None,
+            column: None,
+            data: None,
+        }
+    }
+    /// A successful response carrying the answer to a query.
+    pub fn data(data: impl Serialize) -> Self {
+        match serde_json::to_value(data) {
+            Ok(data) => Self {
+                data: Some(data),
+                ..Self::ok()
+            },
+            Err(e) => Self::error(ErrorKind::Internal, e.to_string()),
+        }
+    }
+    pub fn error(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(kind),
+            message: Some(message.into()),
+            line: None,
+            column: None,
+            data: None,
+        }
+    }
+    /// A parse error located within the connection, for a message starting at `first_line`.
+    pub fn parse_error(e: &serde_json::Error, first_line: usize) -> Self {
+        Self {
+            line: Some(first_line + e.line().saturating_sub(1)),
+            column: Some(e.column()),
+            ..Self::error(ErrorKind::Parse, e.to_string())
+        }
+    }
+}
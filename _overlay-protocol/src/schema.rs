@@ -0,0 +1,15 @@
+use crate::command::Command;
+use crate::response::{Event, Response};
+use schemars::{Schema, schema_for};
+/// Schema of the JSON commands the overlay accepts.
+pub fn command_schema() -> Schema {
+    schema_for!(Command)
+}
+/// Schema of the replies written back over the Unix socket.
+pub fn response_schema() -> Schema {
+    schema_for!(Response)
+}
+/// Schema of the events sent to subscribers.
+pub fn event_schema() -> Schema {
+    schema_for!(Event)
+}
@@ -0,0 +1,88 @@
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::io;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+/// How [`OverlayClient::tcp_tls`] verifies the overlay and, optionally, identifies itself.
+///
+/// [`OverlayClient::tcp_tls`]: crate::OverlayClient::tcp_tls
+pub struct TlsOptions {
+    /// PEM CA certificate the overlay's certificate must be issued by.
+    pub ca: PathBuf,
+    /// Name the overlay's certificate must be valid for, by default the address connected to.
+    pub server_name: Option<String>,
+    /// PEM client certificate chain and private key, for listeners that require one.
+    pub identity: Option<(PathBuf, PathBuf)>,
+}
+impl TlsOptions {
+    pub fn new(ca: impl Into<PathBuf>) -> Self {
+        Self {
+            ca: ca.into(),
+            server_name: None,
+            identity: None,
+        }
+    }
+    pub fn server_name(mut self, name: impl Into<String>) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+    pub fn identity(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.identity = Some((cert.into(), key.into()));
+        self
+    }
+    pub(crate) fn connect(
+        &self,
+        server_name: &str,
+        stream: TcpStream,
+    ) -> io::Result<StreamOwned<ClientConnection, TcpStream>> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.ca)? {
+            roots.add(cert).map_err(io::Error::other)?;
+        }
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots);
+        let config = match &self.identity {
+            Some((cert, key)) => {
+                let key = PrivateKeyDer::from_pem_file(key).map_err(|e| pem_error(key, e))?;
+                builder
+                    .with_client_auth_cert(load_certs(cert)?, key)
+                    .map_err(io::Error::other)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let server_name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let connection =
+            ClientConnection::new(Arc::new(config), server_name).map_err(io::Error::other)?;
+        let mut stream = StreamOwned::new(connection, stream);
+        // Handshake now so certificate errors surface here rather than on the first command
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+        Ok(stream)
+    }
+}
+/// Reads the certificates in a PEM file, failing if there are none.
+pub fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs: Vec<_> = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect())
+        .map_err(|e| pem_error(path, e))?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} holds no certificates", path.display()),
+        ));
+    }
+    Ok(certs)
+}
+fn pem_error(path: &Path, e: rustls::pki_types::pem::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), e),
+    )
+}
@@ -0,0 +1,74 @@
+use crate::color::Brush;
+use crate::coord::Coord;
+use serde::{Deserialize, Serialize};
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Orientation {
+    #[default]
+    Horizontal,
+    /// Fills from the bottom up.
+    Vertical,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ProgressParams {
+    /// Fill level from 0 to 1.
+    pub value: f32,
+    #[serde(default)]
+    pub orientation: Orientation,
+    pub x1: Coord,
+    pub y1: Coord,
+    pub x2: Coord,
+    pub y2: Coord,
+    /// Number of equal segments to divide the bar into with tick lines.
+    #[serde(default)]
+    pub ticks: u32,
+    pub color: Option<Brush>,
+    pub background: Option<Brush>,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ToastParams {
+    pub title: String,
+    pub body: Option<String>,
+    /// PNG or QOI file shown left of the text.
+    pub icon: Option<String>,
+}
+/// A border drawn just inside a rectangle, by default the whole surface.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FrameParams {
+    pub x1: Option<Coord>,
+    pub y1: Option<Coord>,
+    pub x2: Option<Coord>,
+    pub y2: Option<Coord>,
+    pub width: Option<f32>,
+    pub color: Option<Brush>,
+}
+/// A filled circle with short text centred on `x`, `y`.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BadgeParams {
+    pub x: Coord,
+    pub y: Coord,
+    pub text: String,
+    pub color: Option<Brush>,
+}
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Widget {
+    Progress(ProgressParams),
+    Toast(ToastParams),
+    Frame(FrameParams),
+    Badge(BadgeParams),
+}
+impl Widget {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Widget::Progress(_) => "Progress",
+            Widget::Toast(_) => "Toast",
+            Widget::Frame(_) => "Frame",
+            Widget::Badge(_) => "Badge",
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use overlay_protocol::coord::Coord;
+use overlay_protocol::draw::{
+    CircleParams, ClickableParams, DrawOperation, ImageParams, LineParams, PathParams, PixelParams,
+    RectangleParams, TextParams,
+};
+use std::time::{Duration, Instant};
+/// Start keyframe of a running tween: the operations on screen when it began.
+#[derive(Clone)]
+pub struct Tween {
+    pub from: Vec<DrawOperation>,
+    pub start: Instant,
+}
+/// Linear progress of an animation of `duration` started at `start`, in `0.0..=1.0`.
+pub fn progress(start: Instant, duration: Duration, now: Instant) -> f32 {
+    if duration.is_zero() {
+        return 1.0;
+    }
+    (now.saturating_duration_since(start).as_secs_f32() / duration.as_secs_f32()).min(1.0)
+}
+fn lerp_f32(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+fn lerp_option<T: Copy>(
+    from: Option<T>,
+    to: Option<T>,
+    t: f32,
+    lerp: fn(T, T, f32) -> T,
+) -> Option<T> {
+    match (from, to) {
+        (Some(from), Some(to)) => Some(lerp(from, to, t)),
+        _ => to,
+    }
+}
+/// Interpolates the numeric properties of `from` towards `to`. Everything else, and operations
+/// of a different kind, are taken from `to` unchanged.
+pub fn lerp_operation(from: &DrawOperation, to: &DrawOperation, t: f32) -> DrawOperation {
+    match (from, to) {
+        (DrawOperation::Pixel(a), DrawOperation::Pixel(b)) => DrawOperation::Pixel(PixelParams {
+            x: a.x.lerp(b.x, t),
+            y: a.y.lerp(b.y, t),
+            ..b.clone()
+        }),
+        (DrawOperation::Line(a), DrawOperation::Line(b)) => DrawOperation::Line(LineParams {
+            x1: a.x1.lerp(b.x1, t),
+            y1: a.y1.lerp(b.y1, t),
+            x2: a.x2.lerp(b.x2, t),
+            y2: a.y2.lerp(b.y2, t),
+            width: lerp_f32(a.width, b.width, t),
+            ..b.clone()
+        }),
+        (DrawOperation::Circle(a), DrawOperation::Circle(b)) => {
+            DrawOperation::Circle(CircleParams {
+                x: a.x.lerp(b.x, t),
+                y: a.y.lerp(b.y, t),
+                radius: lerp_f32(a.radius, b.radius, t),
+                outline_width: lerp_f32(a.outline_width, b.outline_width, t),
+                ..b.clone()
+            })
+        }
+        (DrawOperation::Rectangle(a), DrawOperation::Rectangle(b)) => {
+            DrawOperation::Rectangle(RectangleParams {
+                x1: a.x1.lerp(b.x1, t),
+                y1: a.y1.lerp(b.y1, t),
+                x2: a.x2.lerp(b.x2, t),
+                y2: a.y2.lerp(b.y2, t),
+                outline_width: lerp_f32(a.outline_width, b.outline_width, t),
+                ..b.clone()
+            })
+        }
+        (DrawOperation::Text(a), DrawOperation::Text(b)) => DrawOperation::Text(TextParams {
+            x: a.x.lerp(b.x, t),
+            y: a.y.lerp(b.y, t),
+            size: lerp_f32(a.size, b.size, t),
+            max_width: lerp_option(a.max_width, b.max_width, t, Coord::lerp),
+            ..b.clone()
+        }),
+        (DrawOperation::Path(a), DrawOperation::Path(b)) => DrawOperation::Path(PathParams {
+            stroke_width: lerp_f32(a.stroke_width, b.stroke_width, t),
+            dash_offset: lerp_f32(a.dash_offset, b.dash_offset, t),
+            ..b.clone()
+        }),
+        (DrawOperation::Image(a), DrawOperation::Image(b)) => DrawOperation::Image(ImageParams {
+            x: a.x.lerp(b.x, t),
+            y: a.y.lerp(b.y, t),
+            width: lerp_option(a.width, b.width, t, Coord::lerp),
+            height: lerp_option(a.height, b.height, t, Coord::lerp),
+            opacity: lerp_option(a.opacity, b.opacity, t, lerp_f32),
+            ..b.clone()
+        }),
+        (DrawOperation::Clickable(a), DrawOperation::Clickable(b)) => {
+            DrawOperation::Clickable(ClickableParams {
+                x1: a.x1.lerp(b.x1, t),
+                y1: a.y1.lerp(b.y1, t),
+                x2: a.x2.lerp(b.x2, t),
+                y2: a.y2.lerp(b.y2, t),
+                ..b.clone()
+            })
+        }
+        _ => to.clone(),
+    }
+}
@@ -0,0 +1,103 @@
+use crate::config::Palette;
+use overlay_protocol::color::{Brush, Gradient};
+use tiny_skia::Color;
+/// Parses a color. `0xAARRGGBB` and `0xRRGGBB`, and the same digits without a prefix, keep
+/// their original meaning with alpha first. Anything else is read as CSS: `#RGB`, `#RGBA`,
+/// `#RRGGBB`, `#RRGGBBAA`, `rgb()`, `rgba()`, `hsl()`, `hsla()` or a color name.
+pub fn parse_color(text: &str) -> Result<Color, String> {
+    let invalid = || format!("invalid color '{}'", text);
+    let text = text.trim();
+    let is_hex =
+        |hex: &str| matches!(hex.len(), 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    let legacy = match text.strip_prefix("0x") {
+        Some(hex) => Some(hex),
+        None if is_hex(text) => Some(text),
+        None => None,
+    };
+    if let Some(hex) = legacy {
+        if !is_hex(hex) {
+            return Err(invalid());
+        }
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+        return Ok(match hex.len() {
+            8 => Color::from_rgba8(byte(2), byte(4), byte(6), byte(0)),
+            _ => Color::from_rgba8(byte(0), byte(2), byte(4), 255),
+        });
+    }
+    let color: svgtypes::Color = text.parse().map_err(|_| invalid())?;
+    Ok(Color::from_rgba8(
+        color.red,
+        color.green,
+        color.blue,
+        color.alpha,
+    ))
+}
+/// Replaces a palette name with the color it stands for.
+pub fn resolve<'a>(palette: &'a Palette, name: &'a str) -> &'a str {
+    palette.get(name).map_or(name, String::as_str)
+}
+/// Checks that a brush can be drawn, looking up palette names.
+pub fn check_brush(brush: &Brush, palette: &Palette) -> Result<(), String> {
+    let stops = match brush {
+        Brush::Solid(color) => return parse_color(resolve(palette, color)).map(|_| ()),
+        Brush::Gradient(Gradient::Linear { stops, .. }) => stops,
+        Brush::Gradient(Gradient::Radial { radius, stops, .. }) => {
+            if *radius <= 0.0 {
+                return Err("gradient radius must be positive".to_string());
+            }
+            stops
+        }
+    };
+    if stops.is_empty() {
+        return Err("gradient needs at least one stop".to_string());
+    }
+    stops
+        .iter()
+        .try_for_each(|stop| parse_color(resolve(palette, &stop.color)).map(|_| ()))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn rgba(text: &str) -> [u8; 4] {
+        let color = parse_color(text).unwrap().to_color_u8();
+        [color.red(), color.green(), color.blue(), color.alpha()]
+    }
+    #[test]
+    fn parses_css_colors() {
+        assert_eq!(rgba("#f80"), [255, 136, 0, 255]);
+        assert_eq!(rgba("#f808"), [255, 136, 0, 136]);
+        assert_eq!(rgba("#ff8800"), [255, 136, 0, 255]);
+        assert_eq!(rgba("#ff880080"), [255, 136, 0, 128]);
+        assert_eq!(rgba("rgb(255, 136, 0)"), [255, 136, 0, 255]);
+        assert_eq!(rgba("rgba(255, 136, 0, 0.5)"), [255, 136, 0, 128]);
+        assert_eq!(rgba("hsl(120, 100%, 50%)"), [0, 255, 0, 255]);
+        assert_eq!(rgba("hsla(120, 100%, 50%, 0.5)"), [0, 255, 0, 128]);
+        assert_eq!(rgba("orange"), [255, 165, 0, 255]);
+        assert_eq!(rgba(" red "), [255, 0, 0, 255]);
+    }
+    #[test]
+    fn parses_legacy_hex_as_argb() {
+        assert_eq!(rgba("0x80FF8800"), [255, 136, 0, 128]);
+        assert_eq!(rgba("0xFF8800"), [255, 136, 0, 255]);
+        assert_eq!(rgba("80FF8800"), [255, 136, 0, 128]);
+        assert_eq!(rgba("FF8800"), [255, 136, 0, 255]);
+    }
+    #[test]
+    fn rejects_invalid_colors() {
+        for text in [
+            "",
+            "0x",
+            "0xFF88",
+            "0xGG8800",
+            "#ff88000",
+            "#xyz",
+            "rgb(1, 2)",
+            "nocolor",
+        ] {
+            assert_eq!(
+                parse_color(text).err(),
+                Some(format!("invalid color '{}'", text))
+            );
+        }
+    }
+}
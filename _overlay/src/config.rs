@@ -0,0 +1,163 @@
+use crate::color::parse_color;
+use crate::widget::Theme;
+use anyhow::{Context, Result};
+use overlay_protocol::config::Listeners;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+/// Color names that commands and themes may use in place of `0xAARRGGBB` values.
+pub type Palette = HashMap<String, String>;
+/// Settings read from `config.toml`. Everything but the listeners is re-read on SIGHUP.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(flatten)]
+    pub listeners: Listeners,
+    /// Allow a TCP listener on a non-loopback address without TLS client certificates.
+    pub insecure: bool,
+    /// Largest command accepted on a socket connection, in bytes.
+    pub max_frame_size: Option<usize>,
+    /// Layer-shell layer the surfaces are placed on.
+    pub layer: SurfaceLayer,
+    pub margin: Margins,
+    /// Output for commands that don't name one: `all`, `focused` or an output name.
+    pub output: Option<String>,
+    /// Widget theme file, relative to the config file's directory.
+    pub theme: Option<PathBuf>,
+    /// Directory that font files named in commands must be in, relative to the config file's
+    /// directory. Without it only installed font families can be used.
+    pub fonts_dir: Option<PathBuf>,
+    /// Directory that image files named in commands must be in, relative to the config file's
+    /// directory. Without it images can only be sent inline.
+    pub images_dir: Option<PathBuf>,
+    pub palette: Palette,
+}
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SurfaceLayer {
+    Background,
+    Bottom,
+    Top,
+    #[default]
+    Overlay,
+}
+/// Space left free at each edge of an output, in logical pixels.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Margins {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+impl Config {
+    /// Reads the config at `path`, or at the default path if none is given. Only a missing
+    /// default file is allowed, and gives the built-in settings.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (mut config, path): (Config, _) = overlay_protocol::config::load(path)?;
+        let Some(path) = path else {
+            return Ok(config);
+        };
+        for (name, color) in &config.palette {
+            parse_color(color)
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("{}: palette entry '{}'", path.display(), name))?;
+        }
+        if let Some(dir) = path.parent() {
+            for file in [
+                &mut config.theme,
+                &mut config.fonts_dir,
+                &mut config.images_dir,
+            ] {
+                *file = file.take().map(|file| dir.join(file));
+            }
+            config.listeners.resolve_paths(dir);
+        }
+        Ok(config)
+    }
+}
+/// Finds a file named in a command inside `dir`, refusing any path that leads out of it so
+/// clients can't have the overlay read arbitrary files. `setting` names the config key for `dir`.
+pub fn resolve_asset(dir: Option<&Path>, path: &str, setting: &str) -> Result<PathBuf> {
+    let dir = dir.with_context(|| format!("file paths need `{}` in the config", setting))?;
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", dir.display()))?;
+    let file = dir
+        .join(path)
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", path))?;
+    if !file.starts_with(&dir) {
+        anyhow::bail!("{} is outside {}", path, dir.display());
+    }
+    Ok(file)
+}
+/// Reads a file, refusing any larger than `max` bytes.
+pub fn read_asset(path: &Path, max: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(max + 1).read_to_end(&mut data))
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    if data.len() as u64 > max {
+        anyhow::bail!("{} is larger than {} bytes", path.display(), max);
+    }
+    Ok(data)
+}
+/// Where the settings come from, kept so they can be re-read on SIGHUP.
+pub struct ConfigSource {
+    pub path: Option<PathBuf>,
+    /// Theme file that takes precedence over the config's.
+    pub theme: Option<PathBuf>,
+}
+impl ConfigSource {
+    /// Reads the config and the widget theme it uses.
+    pub fn load(&self) -> Result<(Config, Theme)> {
+        let config = Config::load(self.path.as_deref())?;
+        let theme = match self.theme.as_ref().or(config.theme.as_ref()) {
+            Some(path) => Theme::load(path)?,
+            None => Theme::default(),
+        };
+        Ok((config, theme))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn assets_stay_inside_their_directory() {
+        let dir = std::env::temp_dir().join(format!("overlay-{}-assets", std::process::id()));
+        std::fs::create_dir_all(dir.join("icons")).unwrap();
+        std::fs::write(dir.join("icons/volume.png"), [0; 64]).unwrap();
+        let inside = resolve_asset(Some(&dir), "icons/volume.png", "images_dir");
+        let absolute = resolve_asset(
+            Some(&dir),
+            &dir.join("icons/volume.png").to_string_lossy(),
+            "images_dir",
+        );
+        let escaping = resolve_asset(
+            Some(&dir.join("icons")),
+            "../icons/../../../etc/passwd",
+            "images_dir",
+        );
+        let outside = resolve_asset(Some(&dir.join("icons")), "/etc/passwd", "images_dir");
+        let undirected = resolve_asset(None, "/etc/passwd", "images_dir");
+        let read = read_asset(&dir.join("icons/volume.png"), 64);
+        let too_large = read_asset(&dir.join("icons/volume.png"), 63);
+        let file = dir.canonicalize().unwrap().join("icons/volume.png");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(inside.unwrap(), file);
+        assert_eq!(absolute.unwrap(), file);
+        assert!(escaping.is_err());
+        assert!(outside.unwrap_err().to_string().contains("outside"));
+        assert!(undirected.unwrap_err().to_string().contains("images_dir"));
+        assert_eq!(read.unwrap().len(), 64);
+        assert!(
+            too_large
+                .unwrap_err()
+                .to_string()
+                .contains("larger than 63 bytes")
+        );
+    }
+}
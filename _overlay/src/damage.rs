@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use tiny_skia::{IntRect, Rect};
+/// Past this many separate regions a frame is redrawn as one bounding box.
+const MAX_DAMAGE_RECTS: usize = 8;
+/// What a layer looked like when it was last drawn onto a surface.
+#[derive(Clone, Copy, PartialEq)]
+pub struct LayerState {
+    pub generation: u64,
+    /// Device pixels covered by the layer, `None` if it draws nothing.
+    pub bounds: Option<IntRect>,
+    pub animating: bool,
+}
+impl LayerState {
+    fn unchanged(&self, now: &LayerState) -> bool {
+        self == now && !self.animating
+    }
+}
+/// Remembers what a surface shows so only the parts that changed get redrawn.
+pub struct DamageTracker {
+    drawn: HashMap<i32, LayerState>,
+    full: bool,
+}
+impl DamageTracker {
+    pub fn new() -> Self {
+        Self {
+            drawn: HashMap::new(),
+            full: true,
+        }
+    }
+    /// Records the layers about to be drawn and returns the regions, in device pixels,
+    /// whose content changed since the previous frame.
+    pub fn update(&mut self, layers: HashMap<i32, LayerState>, surface: IntRect) -> Vec<IntRect> {
+        let mut damage = Vec::new();
+        if self.full {
+            damage.push(surface);
+        }
+        for (id, before) in &self.drawn {
+            match layers.get(id) {
+                Some(now) if before.unchanged(now) => {}
+                now => {
+                    damage.extend(before.bounds);
+                    damage.extend(now.and_then(|l| l.bounds));
+                }
+            }
+        }
+        for (id, now) in &layers {
+            if !self.drawn.contains_key(id) {
+                damage.extend(now.bounds);
+            }
+        }
+        self.drawn = layers;
+        self.full = false;
+        merge(damage, surface)
+    }
+}
+/// Clips rectangles to the surface and combines overlapping ones.
+pub fn merge(rects: Vec<IntRect>, surface: IntRect) -> Vec<IntRect> {
+    let mut merged: Vec<IntRect> = Vec::new();
+    for rect in rects.iter().filter_map(|r| r.intersect(&surface)) {
+        let mut rect = rect;
+        // Absorbing one rectangle can make the result overlap another, so keep going
+        while let Some(i) = merged.iter().position(|m| m.intersect(&rect).is_some()) {
+            rect = union(rect, merged.swap_remove(i));
+        }
+        merged.push(rect);
+    }
+    if merged.len() > MAX_DAMAGE_RECTS {
+        let all = merged.into_iter().reduce(union);
+        return all.into_iter().collect();
+    }
+    merged
+}
+pub fn union(a: IntRect, b: IntRect) -> IntRect {
+    IntRect::from_ltrb(
+        a.left().min(b.left()),
+        a.top().min(b.top()),
+        a.right().max(b.right()),
+        a.bottom().max(b.bottom()),
+    )
+    .unwrap_or(a)
+}
+pub fn union_rect(a: Rect, b: Rect) -> Rect {
+    Rect::from_ltrb(
+        a.left().min(b.left()),
+        a.top().min(b.top()),
+        a.right().max(b.right()),
+        a.bottom().max(b.bottom()),
+    )
+    .unwrap_or(a)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn rect(x: i32, y: i32, w: u32, h: u32) -> IntRect {
+        IntRect::from_xywh(x, y, w, h).unwrap()
+    }
+    fn layer(generation: u64, bounds: IntRect) -> LayerState {
+        LayerState {
+            generation,
+            bounds: Some(bounds),
+            animating: false,
+        }
+    }
+    fn surface() -> IntRect {
+        rect(0, 0, 200, 100)
+    }
+    #[test]
+    fn merges_overlapping_rects() {
+        let damage = merge(vec![rect(0, 0, 10, 10), rect(5, 5, 10, 10)], surface());
+        assert_eq!(damage, vec![rect(0, 0, 15, 15)]);
+        // The union of the first two reaches the third
+        let chain = vec![rect(0, 0, 10, 10), rect(30, 0, 10, 10), rect(5, 0, 30, 5)];
+        assert_eq!(merge(chain, surface()), vec![rect(0, 0, 40, 10)]);
+        let apart = vec![rect(0, 0, 10, 10), rect(50, 50, 10, 10)];
+        assert_eq!(merge(apart.clone(), surface()), apart);
+    }
+    #[test]
+    fn clips_rects_to_the_surface() {
+        let damage = merge(vec![rect(190, 90, 20, 20), rect(300, 0, 5, 5)], surface());
+        assert_eq!(damage, vec![rect(190, 90, 10, 10)]);
+    }
+    #[test]
+    fn caps_the_number_of_rects() {
+        let rects: Vec<_> = (0..8).map(|i| rect(i * 20, 0, 10, 10)).collect();
+        assert_eq!(merge(rects.clone(), surface()).len(), 8);
+        let mut rects = rects;
+        rects.push(rect(0, 50, 10, 10));
+        assert_eq!(merge(rects, surface()), vec![rect(0, 0, 150, 60)]);
+    }
+    #[test]
+    fn first_frame_damages_the_whole_surface() {
+        let mut tracker = DamageTracker::new();
+        let layers = HashMap::from([(1, layer(1, rect(10, 10, 20, 20)))]);
+        assert_eq!(tracker.update(layers.clone(), surface()), vec![surface()]);
+        assert!(tracker.update(layers, surface()).is_empty());
+    }
+    #[test]
+    fn damages_changed_layers_only() {
+        let mut tracker = DamageTracker::new();
+        let layers = HashMap::from([
+            (1, layer(1, rect(10, 10, 20, 20))),
+            (2, layer(1, rect(100, 10, 20, 20))),
+        ]);
+        tracker.update(layers, surface());
+        // Layer 1 moves, layer 2 stays put and a new layer 3 appears
+        let layers = HashMap::from([
+            (1, layer(2, rect(20, 10, 20, 20))),
+            (2, layer(1, rect(100, 10, 20, 20))),
+            (3, layer(1, rect(10, 60, 5, 5))),
+        ]);
+        let mut damage = tracker.update(layers, surface());
+        damage.sort_by_key(|r| (r.y(), r.x()));
+        assert_eq!(damage, vec![rect(10, 10, 30, 20), rect(10, 60, 5, 5)]);
+        // A removed layer damages where it was
+        let layers = HashMap::from([(2, layer(1, rect(100, 10, 20, 20)))]);
+        let mut damage = tracker.update(layers, surface());
+        damage.sort_by_key(|r| (r.y(), r.x()));
+        assert_eq!(damage, vec![rect(20, 10, 20, 20), rect(10, 60, 5, 5)]);
+    }
+    #[test]
+    fn animating_layers_are_always_damaged() {
+        let mut tracker = DamageTracker::new();
+        let mut spinner = layer(1, rect(10, 10, 20, 20));
+        spinner.animating = true;
+        tracker.update(HashMap::from([(1, spinner)]), surface());
+        let damage = tracker.update(HashMap::from([(1, spinner)]), surface());
+        assert_eq!(damage, vec![rect(10, 10, 20, 20)]);
+    }
+}
@@ -1,163 +1,761 @@
-use crate::network::Command;
-use serde::Deserialize;
+use crate::animation::{Tween, lerp_operation, progress};
+use crate::color::{check_brush, parse_color, resolve};
+use crate::config::{Config, Palette};
+use crate::damage::{DamageTracker, LayerState, union_rect};
+use crate::font::FontCache;
+use crate::image::ImageCache;
+use crate::network::{CommandError, FocusedOutput, resolve_output};
+use crate::path::parse_svg_path;
+use crate::widget::{Theme, expand};
+use fontdue::Font;
+use fontdue::layout::{CoordinateSystem, HorizontalAlign, Layout, LayoutSettings, TextStyle};
+use overlay_protocol::animation::Transition;
+use overlay_protocol::color::{Brush, Gradient};
+use overlay_protocol::command::{Command, DrawCommand, LayerAction, OutputTarget};
+use overlay_protocol::coord::{Anchor, Coord};
+use overlay_protocol::draw::{
+    CircleParams, DrawOperation, ImageFilter, ImageParams, LineParams, LineSide, PathFillRule,
+    PathLineCap, PathLineJoin, PathParams, PixelParams, RectangleParams, TextAlign, TextParams,
+};
+use overlay_protocol::response::{Event, LayerDump, LayerSummary, SnapshotInfo};
+use overlay_protocol::widget::Widget;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
-use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
-#[derive(Deserialize, Clone)]
-pub enum LineSide {
-    Left,
-    Right,
-    Center,
-}
-#[derive(Deserialize, Clone)]
-pub struct PixelParams {
-    pub x: i32,
-    pub y: i32,
-    pub color: String,
-}
-#[derive(Deserialize, Clone)]
-pub struct LineParams {
-    pub x1: i32,
-    pub y1: i32,
-    pub x2: i32,
-    pub y2: i32,
-    pub width: f32,
-    pub side: LineSide,
-    pub color: String,
-}
-#[derive(Deserialize, Clone)]
-pub struct CircleParams {
-    pub x: i32,
-    pub y: i32,
-    pub radius: f32,
-    pub fill_color: String,
-    pub outline_width: f32,
-    pub outline_color: String,
-}
-#[derive(Deserialize, Clone)]
-pub struct RectangleParams {
-    pub x1: i32,
-    pub y1: i32,
-    pub x2: i32,
-    pub y2: i32,
-    pub fill_color: String,
-    pub outline_width: f32,
-    pub outline_color: String,
+use tiny_skia::{
+    BlendMode, Color, FillRule, FilterQuality, GradientStop, IntRect, IntSize, LineCap, LineJoin,
+    LinearGradient, Mask, Paint, PathBuilder, Pixmap, PixmapPaint, Point, RadialGradient, Rect,
+    SpreadMode, Stroke, StrokeDash, Transform,
+};
+/// Checks that every color in an operation parses, looking up palette names.
+fn check_operation_colors(operation: &DrawOperation, palette: &Palette) -> Result<(), String> {
+    match operation {
+        DrawOperation::Pixel(p) => check_brush(&p.color, palette),
+        DrawOperation::Line(p) => check_brush(&p.color, palette),
+        DrawOperation::Circle(p) => {
+            check_brush(&p.fill_color, palette)?;
+            check_brush(&p.outline_color, palette)
+        }
+        DrawOperation::Rectangle(p) => {
+            check_brush(&p.fill_color, palette)?;
+            check_brush(&p.outline_color, palette)
+        }
+        DrawOperation::Text(p) => check_brush(&p.color, palette),
+        DrawOperation::Path(p) => p
+            .fill_color
+            .iter()
+            .chain(&p.stroke_color)
+            .try_for_each(|brush| check_brush(brush, palette)),
+        DrawOperation::Image(_) | DrawOperation::Clickable(_) => Ok(()),
+    }
 }
-#[derive(Deserialize, Clone)]
-pub enum DrawOperation {
-    Pixel(PixelParams),
-    Line(LineParams),
-    Circle(CircleParams),
-    Rectangle(RectangleParams),
+/// A clickable region resolved against a surface, in logical coordinates.
+pub struct Clickable<'a> {
+    pub layer: i32,
+    pub id: &'a str,
+    pub rect: Rect,
 }
 pub struct Layer {
     pub operations: Vec<DrawOperation>,
     pub expiry: Option<Instant>,
+    pub visible: bool,
+    pub transition: Transition,
+    pub shown_at: Instant,
+    pub tween: Option<Tween>,
+    pub output: OutputTarget,
+    pub anchor: Anchor,
+    /// Changes whenever the operations do, so surfaces know to redraw the layer.
+    pub generation: u64,
+}
+impl Layer {
+    fn new(
+        operations: Vec<DrawOperation>,
+        expiry: Option<Instant>,
+        transition: Transition,
+    ) -> Self {
+        Self {
+            operations,
+            expiry,
+            visible: true,
+            transition,
+            shown_at: Instant::now(),
+            tween: None,
+            output: OutputTarget::All,
+            anchor: Anchor::TopLeft,
+            generation: 0,
+        }
+    }
+    /// Combined fade-in and fade-out opacity at `now`.
+    pub fn opacity(&self, now: Instant) -> f32 {
+        let easing = self.transition.easing;
+        let fade_in = easing.apply(progress(self.shown_at, self.transition.fade_in(), now));
+        let fade_out = match self.expiry {
+            Some(expiry) if now >= expiry => {
+                1.0 - easing.apply(progress(expiry, self.transition.fade_out(), now))
+            }
+            _ => 1.0,
+        };
+        fade_in * fade_out
+    }
+    /// The operations to draw at `now`, interpolated while a tween is running.
+    pub fn operations_at(&self, now: Instant) -> Cow<'_, [DrawOperation]> {
+        match &self.tween {
+            Some(tween) if progress(tween.start, self.transition.tween(), now) < 1.0 => {
+                let t = self.transition.easing.apply(progress(
+                    tween.start,
+                    self.transition.tween(),
+                    now,
+                ));
+                Cow::Owned(
+                    tween
+                        .from
+                        .iter()
+                        .zip(&self.operations)
+                        .map(|(from, to)| lerp_operation(from, to, t))
+                        .collect(),
+                )
+            }
+            _ => Cow::Borrowed(&self.operations),
+        }
+    }
+    pub fn is_animating(&self, now: Instant) -> bool {
+        self.visible
+            && (progress(self.shown_at, self.transition.fade_in(), now) < 1.0
+                || self
+                    .tween
+                    .as_ref()
+                    .is_some_and(|t| progress(t.start, self.transition.tween(), now) < 1.0)
+                || self.expiry.is_some_and(|e| now >= e))
+    }
+    /// When the layer disappears for good, after its fade-out has finished.
+    fn removal_time(&self) -> Option<Instant> {
+        self.expiry.map(|e| e + self.transition.fade_out())
+    }
 }
 pub struct CanvasState {
     layers: HashMap<i32, Layer>,
+    fonts: FontCache,
+    images: ImageCache,
+    generation: u64,
+    theme: Theme,
+    palette: Rc<Palette>,
+    /// Output for commands that don't name one.
+    default_output: Option<OutputTarget>,
+    pub focused_output: FocusedOutput,
+    /// Events for subscribers raised since they were last taken.
+    events: Vec<Event>,
 }
 impl CanvasState {
     pub fn new() -> Self {
         Self {
             layers: HashMap::new(),
+            fonts: FontCache::new(),
+            images: ImageCache::new(),
+            generation: 0,
+            theme: Theme::default(),
+            palette: Rc::new(Palette::new()),
+            default_output: None,
+            focused_output: FocusedOutput::default(),
+            events: Vec::new(),
         }
     }
-    pub fn update(&mut self, command: Command) {
-        let layer_id = command.layer.unwrap_or(0);
-        let expiry = command
-            .timeout_ms
-            .map(|ms| Instant::now() + Duration::from_millis(ms));
-        self.layers.insert(
-            layer_id,
-            Layer {
-                operations: command.operations,
-                expiry,
-            },
+    /// Applies the parts of the config that affect drawing, redrawing every layer since
+    /// palette colors may have changed.
+    pub fn apply_config(&mut self, config: &Config, theme: Theme) {
+        self.theme = theme;
+        self.palette = Rc::new(config.palette.clone());
+        self.default_output = config.output.clone().map(OutputTarget::from);
+        self.fonts.set_dir(config.fonts_dir.clone());
+        self.images.set_dir(config.images_dir.clone());
+        let generation = self.next_generation();
+        for layer in self.layers.values_mut() {
+            layer.generation = generation;
+        }
+    }
+    pub fn update(&mut self, command: Command) -> Result<(), CommandError> {
+        match command {
+            Command::Replace(command) => {
+                self.check_colors(&command.operations)?;
+                let layer_id = command.layer.unwrap_or(0);
+                let now = Instant::now();
+                let mut layer = self.new_layer(
+                    command.operations,
+                    Self::expiry(command.timeout_ms),
+                    command.transition.unwrap_or_default(),
+                    command.output,
+                );
+                layer.anchor = command.anchor;
+                self.events.push(Event::LayerReplaced { layer: layer_id });
+                if let Some(previous) = self.layers.get(&layer_id) {
+                    // Pick up from what is currently on screen instead of restarting the fade-in
+                    let fade_done = layer.transition.fade_in().mul_f32(previous.opacity(now));
+                    layer.shown_at = now.checked_sub(fade_done).unwrap_or(now);
+                    if !layer.transition.tween().is_zero()
+                        && previous.operations.len() == layer.operations.len()
+                    {
+                        layer.tween = Some(Tween {
+                            from: previous.operations_at(now).into_owned(),
+                            start: now,
+                        });
+                    }
+                }
+                self.layers.insert(layer_id, layer);
+            }
+            Command::Widget(command) => {
+                let (layer, timeout_ms, anchor, slot) = match command.widget {
+                    Widget::Toast(_) => {
+                        let toast = &self.theme.toast;
+                        let base = command.layer.unwrap_or(toast.layer);
+                        let slot = self.toast_slot(base, toast.max_stack.max(1));
+                        let timeout_ms = command.timeout_ms.or(toast.timeout_ms);
+                        let anchor = command.anchor.unwrap_or(toast.anchor);
+                        (base + slot as i32, timeout_ms, anchor, slot)
+                    }
+                    _ => (
+                        command.layer.unwrap_or(0),
+                        command.timeout_ms,
+                        command.anchor.unwrap_or_default(),
+                        0,
+                    ),
+                };
+                let operations = expand(command.widget, &self.theme, slot);
+                return self.update(Command::Replace(DrawCommand {
+                    layer: Some(layer),
+                    timeout_ms,
+                    operations,
+                    transition: command.transition,
+                    output: command.output,
+                    anchor,
+                }));
+            }
+            Command::Action(LayerAction::Clear { layer }) => {
+                if self.layers.remove(&layer).is_none() {
+                    return Err(CommandError::UnknownLayer(layer));
+                }
+                self.events.push(Event::LayerCleared { layer });
+            }
+            Command::Action(LayerAction::ClearAll) => {
+                let mut cleared: Vec<i32> = self.layers.drain().map(|(id, _)| id).collect();
+                cleared.sort();
+                self.events.extend(
+                    cleared
+                        .into_iter()
+                        .map(|layer| Event::LayerCleared { layer }),
+                );
+            }
+            Command::Action(LayerAction::Append { layer, operations }) => {
+                self.check_colors(&operations)?;
+                self.events.push(Event::LayerReplaced { layer });
+                if !self.layers.contains_key(&layer) {
+                    let new = self.new_layer(Vec::new(), None, Transition::default(), None);
+                    self.layers.insert(layer, new);
+                }
+                let layer = self.layer_mut(layer)?;
+                layer.tween = None;
+                layer.operations.extend(operations);
+            }
+            Command::Action(LayerAction::SetTimeout { layer, ms }) => {
+                self.layer_mut(layer)?.expiry = Self::expiry(ms);
+            }
+            Command::Action(LayerAction::Hide { layer }) => self.layer_mut(layer)?.visible = false,
+            Command::Action(LayerAction::Show { layer }) => self.layer_mut(layer)?.visible = true,
+            // Queries only read state and are answered by the main loop, subscriptions by the
+            // connection that made them
+            Command::Query(_) | Command::Subscription(_) => {}
+        }
+        Ok(())
+    }
+    pub fn dump_layer(&self, layer: i32) -> Result<LayerDump<'_>, CommandError> {
+        let operations = &self
+            .layers
+            .get(&layer)
+            .ok_or(CommandError::UnknownLayer(layer))?
+            .operations;
+        Ok(LayerDump { layer, operations })
+    }
+    pub fn layer_summaries(&self) -> Vec<LayerSummary> {
+        let now = Instant::now();
+        let mut summaries: Vec<LayerSummary> = self
+            .layers
+            .iter()
+            .map(|(&id, layer)| LayerSummary {
+                layer: id,
+                operations: layer.operations.len(),
+                visible: layer.visible,
+                remaining_ms: layer
+                    .expiry
+                    .map(|e| e.saturating_duration_since(now).as_millis() as u64),
+                output: layer.output.clone(),
+                anchor: layer.anchor,
+            })
+            .collect();
+        summaries.sort_by_key(|s| s.layer);
+        summaries
+    }
+    /// A layer shown on `output`, or on the configured default output if none is given.
+    fn new_layer(
+        &mut self,
+        operations: Vec<DrawOperation>,
+        expiry: Option<Instant>,
+        transition: Transition,
+        output: Option<OutputTarget>,
+    ) -> Layer {
+        let mut layer = Layer::new(operations, expiry, transition);
+        layer.output = resolve_output(
+            output
+                .or_else(|| self.default_output.clone())
+                .unwrap_or_default(),
+            &self.focused_output,
         );
+        layer.generation = self.next_generation();
+        layer
+    }
+    /// Looks up a layer about to be changed, so surfaces redraw it.
+    fn layer_mut(&mut self, layer: i32) -> Result<&mut Layer, CommandError> {
+        let generation = self.next_generation();
+        let layer = self
+            .layers
+            .get_mut(&layer)
+            .ok_or(CommandError::UnknownLayer(layer))?;
+        layer.generation = generation;
+        Ok(layer)
+    }
+    /// Rejects operations with colors that don't parse, so the client hears about it
+    /// instead of seeing black.
+    fn check_colors(&self, operations: &[DrawOperation]) -> Result<(), CommandError> {
+        operations
+            .iter()
+            .try_for_each(|op| check_operation_colors(op, &self.palette))
+            .map_err(CommandError::InvalidColor)
+    }
+    fn next_generation(&mut self) -> u64 {
+        self.generation += 1;
+        self.generation
+    }
+    /// Position for a new toast in the stack whose first layer is `base`: the first one free,
+    /// or the oldest when all are taken.
+    fn toast_slot(&self, base: i32, max_stack: usize) -> usize {
+        let now = Instant::now();
+        let shown = |slot: usize| {
+            self.layers
+                .get(&(base + slot as i32))
+                .filter(|layer| layer.removal_time().is_none_or(|t| t > now))
+                .map(|layer| layer.shown_at)
+        };
+        (0..max_stack)
+            .find(|&slot| shown(slot).is_none())
+            .or_else(|| (0..max_stack).min_by_key(|&slot| shown(slot)))
+            .unwrap_or(0)
+    }
+    fn expiry(timeout_ms: Option<u64>) -> Option<Instant> {
+        timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms))
     }
     pub fn prune(&mut self) -> bool {
         let now = Instant::now();
         let before = self.layers.len();
-        self.layers
-            .retain(|_, layer| layer.expiry.map_or(true, |e| e > now));
+        let events = &mut self.events;
+        self.layers.retain(|&id, layer| {
+            let keep = layer.removal_time().is_none_or(|t| t > now);
+            if !keep {
+                events.push(Event::LayerExpired { layer: id });
+            }
+            keep
+        });
         let after = self.layers.len();
         before != after
     }
-    pub fn render(&self, renderer: &mut Renderer) {
-        renderer.pixmap.fill(Color::TRANSPARENT);
-        let mut sorted_layers: Vec<_> = self.layers.iter().collect();
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+    pub fn is_animating(&self) -> bool {
+        let now = Instant::now();
+        self.layers.values().any(|layer| layer.is_animating(now))
+    }
+    /// Clickable regions of the layers shown on `output`, topmost first.
+    pub fn clickables(&self, renderer: &Renderer, output: Option<&str>) -> Vec<Clickable<'_>> {
+        let now = Instant::now();
+        let mut layers: Vec<_> = self
+            .layers
+            .iter()
+            .filter(|(_, l)| l.visible && l.output.matches(output))
+            .filter(|(_, l)| l.expiry.is_none_or(|e| e > now))
+            .collect();
+        layers.sort_by_key(|(z, _)| std::cmp::Reverse(**z));
+        let mut clickables = Vec::new();
+        for (&layer_id, layer) in layers {
+            for op in layer.operations.iter().rev() {
+                let DrawOperation::Clickable(p) = op else {
+                    continue;
+                };
+                let (x1, y1) = renderer.resolve(layer.anchor, p.x1, p.y1);
+                let (x2, y2) = renderer.resolve(layer.anchor, p.x2, p.y2);
+                if let Some(rect) = ltrb_around(x1, y1, x2, y2) {
+                    clickables.push(Clickable {
+                        layer: layer_id,
+                        id: &p.id,
+                        rect,
+                    });
+                }
+            }
+        }
+        clickables
+    }
+    /// The next time a layer starts fading out or is removed.
+    pub fn next_deadline(&self, now: Instant) -> Option<Instant> {
+        self.layers
+            .values()
+            .flat_map(|layer| [layer.expiry, layer.removal_time()])
+            .flatten()
+            .filter(|&t| t > now)
+            .min()
+    }
+    /// Redraws the parts of the renderer's pixmap that changed since it was last drawn,
+    /// showing every visible layer targeting `output`. Returns the redrawn regions in
+    /// device pixels, empty if nothing changed.
+    pub fn render(&mut self, renderer: &mut Renderer, output: Option<&str>) -> Vec<IntRect> {
+        let now = Instant::now();
+        renderer.palette = self.palette.clone();
+        let mut sorted_layers: Vec<_> = self
+            .layers
+            .iter()
+            .filter(|(_, l)| l.visible && l.output.matches(output))
+            .collect();
         sorted_layers.sort_by_key(|(z, _)| *z);
-        for (_, layer) in sorted_layers {
-            for op in &layer.operations {
-                self.draw_operation(renderer, op);
+        let surface = renderer.device_rect();
+        let mut states = HashMap::new();
+        let mut visible = Vec::new();
+        for (&id, layer) in sorted_layers {
+            let opacity = layer.opacity(now);
+            let operations = layer.operations_at(now);
+            renderer.anchor = layer.anchor;
+            let bounds = if opacity > 0.0 {
+                Self::layer_bounds(&mut self.fonts, &mut self.images, renderer, &operations)
+            } else {
+                None
+            };
+            let state = LayerState {
+                generation: layer.generation,
+                bounds,
+                animating: layer.is_animating(now),
+            };
+            states.insert(id, state);
+            if let Some(bounds) = bounds {
+                visible.push((layer.anchor, opacity, operations, bounds));
+            }
+        }
+        let damage = renderer.damage.update(states, surface);
+        for rect in &damage {
+            let mut region = renderer.region(*rect);
+            for (anchor, opacity, operations, bounds) in &visible {
+                if bounds.intersect(rect).is_none() {
+                    continue;
+                }
+                region.anchor = *anchor;
+                if *opacity >= 1.0 {
+                    for op in operations.iter() {
+                        Self::draw_operation(&mut self.fonts, &mut self.images, &mut region, op);
+                    }
+                    continue;
+                }
+                // Translucent layers are composited as a whole so overlapping operations don't
+                // show through
+                let mut scratch = region.region(*rect);
+                scratch.anchor = *anchor;
+                for op in operations.iter() {
+                    Self::draw_operation(&mut self.fonts, &mut self.images, &mut scratch, op);
+                }
+                let paint = PixmapPaint {
+                    opacity: *opacity,
+                    ..PixmapPaint::default()
+                };
+                region.pixmap.draw_pixmap(
+                    0,
+                    0,
+                    scratch.pixmap.as_ref(),
+                    &paint,
+                    Transform::identity(),
+                    None,
+                );
+            }
+            let paint = PixmapPaint {
+                blend_mode: BlendMode::Source,
+                ..PixmapPaint::default()
+            };
+            renderer.pixmap.draw_pixmap(
+                rect.x(),
+                rect.y(),
+                region.pixmap.as_ref(),
+                &paint,
+                Transform::identity(),
+                None,
+            );
+        }
+        damage
+    }
+    /// Device pixels a layer's operations may touch, padded for antialiasing and clipped
+    /// to the surface.
+    fn layer_bounds(
+        fonts: &mut FontCache,
+        images: &mut ImageCache,
+        renderer: &Renderer,
+        operations: &[DrawOperation],
+    ) -> Option<IntRect> {
+        operations
+            .iter()
+            .filter_map(|op| Self::operation_bounds(fonts, images, renderer, op))
+            .reduce(union_rect)?
+            .outset(1.0, 1.0)?
+            .round_out()?
+            .intersect(&renderer.device_rect())
+    }
+    fn operation_bounds(
+        fonts: &mut FontCache,
+        images: &mut ImageCache,
+        renderer: &Renderer,
+        op: &DrawOperation,
+    ) -> Option<Rect> {
+        // Logical bounds, with half the stroke or outline width to spare
+        let bounds = match op {
+            DrawOperation::Pixel(p) => Rect::from_xywh(renderer.x(p.x), renderer.y(p.y), 1.0, 1.0)?,
+            DrawOperation::Line(p) => ltrb_around(
+                renderer.x(p.x1),
+                renderer.y(p.y1),
+                renderer.x(p.x2),
+                renderer.y(p.y2),
+            )?
+            .outset(p.width, p.width)?,
+            DrawOperation::Circle(p) => {
+                let radius = p.radius + p.outline_width;
+                Rect::from_ltrb(
+                    renderer.x(p.x) - radius,
+                    renderer.y(p.y) - radius,
+                    renderer.x(p.x) + radius,
+                    renderer.y(p.y) + radius,
+                )?
+            }
+            DrawOperation::Rectangle(p) => ltrb_around(
+                renderer.x(p.x1),
+                renderer.y(p.y1),
+                renderer.x(p.x2),
+                renderer.y(p.y2),
+            )?,
+            DrawOperation::Text(p) => {
+                let font = fonts.get(p.font.as_deref());
+                // Text is laid out in device pixels already
+                return renderer
+                    .layout_text(p, font)
+                    .glyphs()
+                    .iter()
+                    .filter_map(|g| Rect::from_xywh(g.x, g.y, g.width as f32, g.height as f32))
+                    .reduce(union_rect);
+            }
+            DrawOperation::Path(p) => {
+                let path = parse_svg_path(&p.d).ok()?;
+                // Miter joins can reach well past half the stroke width
+                let spread = if p.stroke_color.is_some() {
+                    p.stroke_width * 2.0
+                } else {
+                    0.0
+                };
+                path.bounds()
+                    .transform(Transform::from_translate(
+                        renderer.x(Coord::default()),
+                        renderer.y(Coord::default()),
+                    ))?
+                    .outset(spread, spread)?
             }
+            DrawOperation::Clickable(_) => return None,
+            DrawOperation::Image(p) => {
+                let image = Self::load_image(images, p).ok()?;
+                let (width, height) = renderer.image_size(p, &image);
+                Rect::from_xywh(renderer.x(p.x), renderer.y(p.y), width, height)?
+            }
+        };
+        bounds.transform(Transform::from_scale(renderer.scale, renderer.scale))
+    }
+    fn load_image(images: &mut ImageCache, params: &ImageParams) -> anyhow::Result<Rc<Pixmap>> {
+        match (&params.path, &params.data) {
+            (Some(path), _) => images.load_path(path),
+            (None, Some(data)) => images.load_base64(data),
+            (None, None) => Err(anyhow::anyhow!("image needs a path or data")),
         }
     }
-    pub fn draw_operation(&self, renderer: &mut Renderer, op: &DrawOperation) {
+    pub fn draw_operation(
+        fonts: &mut FontCache,
+        images: &mut ImageCache,
+        renderer: &mut Renderer,
+        op: &DrawOperation,
+    ) {
         match op {
             DrawOperation::Pixel(p) => renderer.draw_pixel(p.clone()),
             DrawOperation::Line(p) => renderer.draw_line(p.clone()),
             DrawOperation::Circle(p) => renderer.draw_circle(p.clone()),
             DrawOperation::Rectangle(p) => renderer.draw_rectangle(p.clone()),
+            DrawOperation::Text(p) => {
+                let font = fonts.get(p.font.as_deref());
+                renderer.draw_text(p.clone(), font)
+            }
+            DrawOperation::Path(p) => renderer.draw_path(p.clone()),
+            DrawOperation::Image(p) => match Self::load_image(images, p) {
+                Ok(image) => renderer.draw_image(p.clone(), &image),
+                Err(e) => log::error!("Failed to load image: {:#}", e),
+            },
+            DrawOperation::Clickable(_) => {}
         }
     }
 }
 pub struct Renderer {
     pub pixmap: Pixmap,
+    /// Logical surface size that draw coordinates refer to.
+    pub width: u32,
+    pub height: u32,
+    /// Device pixels per logical pixel.
+    pub scale: f32,
+    /// Origin for coordinates of the layer being drawn.
+    pub anchor: Anchor,
+    /// Device pixel position of the pixmap within the surface, when drawing only a region.
+    origin: (i32, i32),
+    pub damage: DamageTracker,
+    palette: Rc<Palette>,
 }
 impl Renderer {
+    /// Writes the last rendered frame to a PNG file.
+    pub fn save_png(&self, path: &str) -> Result<SnapshotInfo, CommandError> {
+        self.pixmap
+            .save_png(path)
+            .map_err(|e| CommandError::Snapshot(format!("{}: {}", path, e)))?;
+        Ok(SnapshotInfo {
+            path: path.to_string(),
+            width: self.pixmap.width(),
+            height: self.pixmap.height(),
+        })
+    }
     pub fn new(width: u32, height: u32) -> Self {
-        let pixmap = Pixmap::new(width, height).expect("Failed to create pixmap");
-        Self { pixmap }
-    }
-    fn parse_color(hex: &str) -> Color {
-        let hex = hex
-            .strip_prefix("0x")
-            .or_else(|| hex.strip_prefix("#"))
-            .unwrap_or(hex);
-        let (r, g, b, a) = if hex.len() == 8 {
-            let a = u8::from_str_radix(&hex[0..2], 16).unwrap_or(255);
-            let r = u8::from_str_radix(&hex[2..4], 16).unwrap_or(0);
-            let g = u8::from_str_radix(&hex[4..6], 16).unwrap_or(0);
-            let b = u8::from_str_radix(&hex[6..8], 16).unwrap_or(0);
-            (r, g, b, a)
-        } else if hex.len() == 6 {
-            let r = u8::from_str_radix(&hex[0..2], 16).unwrap_or(0);
-            let g = u8::from_str_radix(&hex[2..4], 16).unwrap_or(0);
-            let b = u8::from_str_radix(&hex[4..6], 16).unwrap_or(0);
-            (r, g, b, 255)
-        } else {
-            (0, 0, 0, 255)
-        };
-        let a_f = a as f32 / 255.0;
-        Color::from_rgba(
-            (r as f32 / 255.0) * a_f,
-            (g as f32 / 255.0) * a_f,
-            (b as f32 / 255.0) * a_f,
-            a_f,
+        Self::with_scale(width, height, 1.0)
+    }
+    /// Allocates the pixmap at native device resolution for a logical `width`x`height` surface.
+    pub fn with_scale(width: u32, height: u32, scale: f32) -> Self {
+        let pixmap = Pixmap::new(
+            (width as f32 * scale).ceil() as u32,
+            (height as f32 * scale).ceil() as u32,
         )
-        .unwrap()
+        .expect("Failed to create pixmap");
+        Self {
+            pixmap,
+            width,
+            height,
+            scale,
+            anchor: Anchor::TopLeft,
+            origin: (0, 0),
+            damage: DamageTracker::new(),
+            palette: Rc::new(Palette::new()),
+        }
     }
-    pub fn draw_pixel(&mut self, params: PixelParams) {
-        let x = params.x as f32;
-        let y = params.y as f32;
-        let color = Self::parse_color(&params.color);
+    /// A renderer for the same surface that only covers `rect`, in device pixels.
+    fn region(&self, rect: IntRect) -> Renderer {
+        Self {
+            pixmap: Pixmap::new(rect.width(), rect.height()).expect("Failed to create pixmap"),
+            width: self.width,
+            height: self.height,
+            scale: self.scale,
+            anchor: self.anchor,
+            origin: (rect.x(), rect.y()),
+            damage: DamageTracker::new(),
+            palette: self.palette.clone(),
+        }
+    }
+    fn device_rect(&self) -> IntRect {
+        IntRect::from_xywh(0, 0, self.pixmap.width(), self.pixmap.height())
+            .expect("pixmap has a non-zero size")
+    }
+    /// Maps logical draw coordinates onto device pixels.
+    fn transform(&self) -> Transform {
+        Transform::from_scale(self.scale, self.scale)
+            .post_translate(-self.origin.0 as f32, -self.origin.1 as f32)
+    }
+    fn x(&self, coord: Coord) -> f32 {
+        self.resolve(self.anchor, coord, Coord::default()).0
+    }
+    fn y(&self, coord: Coord) -> f32 {
+        self.resolve(self.anchor, Coord::default(), coord).1
+    }
+    /// Resolves a point to logical surface coordinates relative to `anchor`.
+    fn resolve(&self, anchor: Anchor, x: Coord, y: Coord) -> (f32, f32) {
+        let (fx, fy) = anchor.fractions();
+        let (width, height) = (self.width as f32, self.height as f32);
+        (x.resolve(fx * width, width), y.resolve(fy * height, height))
+    }
+    /// Looks up palette names; colors that don't parse, which commands are checked for
+    /// up front, draw black.
+    fn color(&self, name: &str) -> Color {
+        parse_color(resolve(&self.palette, name)).unwrap_or(Color::BLACK)
+    }
+    /// Builds the paint for a brush, resolving gradient coordinates like shape coordinates.
+    fn paint(&self, brush: &Brush) -> Paint<'static> {
         let mut paint = Paint::default();
-        paint.set_color(color);
+        let stops = |stops: &[overlay_protocol::color::GradientStop]| {
+            stops
+                .iter()
+                .map(|stop| GradientStop::new(stop.offset, self.color(&stop.color)))
+                .collect()
+        };
+        let shader = match brush {
+            Brush::Solid(color) => {
+                paint.set_color(self.color(color));
+                return paint;
+            }
+            Brush::Gradient(Gradient::Linear {
+                x1,
+                y1,
+                x2,
+                y2,
+                stops: s,
+            }) => LinearGradient::new(
+                Point::from_xy(self.x(*x1), self.y(*y1)),
+                Point::from_xy(self.x(*x2), self.y(*y2)),
+                stops(s),
+                SpreadMode::Pad,
+                Transform::identity(),
+            ),
+            Brush::Gradient(Gradient::Radial {
+                x,
+                y,
+                radius,
+                stops: s,
+            }) => {
+                let center = Point::from_xy(self.x(*x), self.y(*y));
+                RadialGradient::new(
+                    center,
+                    center,
+                    *radius,
+                    stops(s),
+                    SpreadMode::Pad,
+                    Transform::identity(),
+                )
+            }
+        };
+        match shader {
+            Some(shader) => paint.shader = shader,
+            None => paint.set_color(Color::TRANSPARENT),
+        }
+        paint
+    }
+    pub fn draw_pixel(&mut self, params: PixelParams) {
+        let x = self.x(params.x);
+        let y = self.y(params.y);
+        let paint = self.paint(&params.color);
         if let Some(rect) = Rect::from_xywh(x, y, 1.0, 1.0) {
-            self.pixmap
-                .fill_rect(rect, &paint, Transform::identity(), None);
+            self.pixmap.fill_rect(rect, &paint, self.transform(), None);
         }
     }
     pub fn draw_line(&mut self, params: LineParams) {
-        let x1 = params.x1 as f32;
-        let y1 = params.y1 as f32;
-        let x2 = params.x2 as f32;
-        let y2 = params.y2 as f32;
+        let x1 = self.x(params.x1);
+        let y1 = self.y(params.y1);
+        let x2 = self.x(params.x2);
+        let y2 = self.y(params.y2);
         let width = params.width;
-        let color = Self::parse_color(&params.color);
+        let paint = self.paint(&params.color);
         let dx = x2 - x1;
         let dy = y2 - y1;
         let length = (dx * dx + dy * dy).sqrt();
@@ -179,57 +777,51 @@ impl Renderer {
         path.move_to(start_x, start_y);
         path.line_to(end_x, end_y);
         if let Some(path) = path.finish() {
-            let mut paint = Paint::default();
-            paint.set_color(color);
             let mut stroke = Stroke::default();
             stroke.width = width;
             self.pixmap
-                .stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+                .stroke_path(&path, &paint, &stroke, self.transform(), None);
         }
     }
     pub fn draw_circle(&mut self, params: CircleParams) {
-        let cx = params.x as f32;
-        let cy = params.y as f32;
+        let cx = self.x(params.x);
+        let cy = self.y(params.y);
         let radius = params.radius;
-        let fill_color = Self::parse_color(&params.fill_color);
-        let outline_color = Self::parse_color(&params.outline_color);
+        let fill_paint = self.paint(&params.fill_color);
+        let outline_paint = self.paint(&params.outline_color);
         let outline_width = params.outline_width;
         let mut path = PathBuilder::new();
         path.push_circle(cx, cy, radius);
         if let Some(path) = path.finish() {
-            let mut paint = Paint::default();
-            paint.set_color(outline_color);
             let mut stroke = Stroke::default();
             stroke.width = outline_width;
             self.pixmap
-                .stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+                .stroke_path(&path, &outline_paint, &stroke, self.transform(), None);
         }
         let inner_radius = radius - outline_width;
         if inner_radius > 0.0 {
             let mut inner_path = PathBuilder::new();
             inner_path.push_circle(cx, cy, inner_radius);
             if let Some(inner_path) = inner_path.finish() {
-                let mut fill_paint = Paint::default();
-                fill_paint.set_color(fill_color);
                 self.pixmap.fill_path(
                     &inner_path,
                     &fill_paint,
                     FillRule::Winding,
-                    Transform::identity(),
+                    self.transform(),
                     None,
                 );
             }
         }
     }
     pub fn draw_rectangle(&mut self, params: RectangleParams) {
-        let x1 = params.x1 as f32;
-        let y1 = params.y1 as f32;
-        let x2 = params.x2 as f32;
-        let y2 = params.y2 as f32;
+        let x1 = self.x(params.x1);
+        let y1 = self.y(params.y1);
+        let x2 = self.x(params.x2);
+        let y2 = self.y(params.y2);
         let width = x2 - x1;
         let height = y2 - y1;
-        let fill_color = Self::parse_color(&params.fill_color);
-        let outline_color = Self::parse_color(&params.outline_color);
+        let fill_paint = self.paint(&params.fill_color);
+        let outline_paint = self.paint(&params.outline_color);
         let outline_width = params.outline_width;
         // Draw fill on the inner area
         let inner_x = x1 + outline_width;
@@ -238,20 +830,16 @@ impl Renderer {
         let inner_height = height - 2.0 * outline_width;
         if inner_width > 0.0 && inner_height > 0.0 {
             if let Some(inner_rect) = Rect::from_xywh(inner_x, inner_y, inner_width, inner_height) {
-                let mut fill_paint = Paint::default();
-                fill_paint.set_color(fill_color);
                 self.pixmap
-                    .fill_rect(inner_rect, &fill_paint, Transform::identity(), None);
+                    .fill_rect(inner_rect, &fill_paint, self.transform(), None);
             }
         }
         // Draw outline as filled border strips inside the rectangle
-        let mut outline_paint = Paint::default();
-        outline_paint.set_color(outline_color);
         // Left border
         if outline_width > 0.0 {
             if let Some(left_rect) = Rect::from_xywh(x1, y1, outline_width, height) {
                 self.pixmap
-                    .fill_rect(left_rect, &outline_paint, Transform::identity(), None);
+                    .fill_rect(left_rect, &outline_paint, self.transform(), None);
             }
         }
         // Right border
@@ -259,7 +847,7 @@ impl Renderer {
             if let Some(right_rect) = Rect::from_xywh(x2 - outline_width, y1, outline_width, height)
             {
                 self.pixmap
-                    .fill_rect(right_rect, &outline_paint, Transform::identity(), None);
+                    .fill_rect(right_rect, &outline_paint, self.transform(), None);
             }
         }
         // Top border (excluding corners already drawn)
@@ -271,7 +859,7 @@ impl Renderer {
                 outline_width,
             ) {
                 self.pixmap
-                    .fill_rect(top_rect, &outline_paint, Transform::identity(), None);
+                    .fill_rect(top_rect, &outline_paint, self.transform(), None);
             }
         }
         // Bottom border (excluding corners already drawn)
@@ -283,8 +871,282 @@ impl Renderer {
                 outline_width,
             ) {
                 self.pixmap
-                    .fill_rect(bottom_rect, &outline_paint, Transform::identity(), None);
+                    .fill_rect(bottom_rect, &outline_paint, self.transform(), None);
             }
         }
     }
+    /// Positions the glyphs of a text operation in device pixels.
+    fn layout_text(&self, params: &TextParams, font: &Font) -> Layout {
+        // Lay out and rasterize at device resolution so glyphs stay crisp when scaled
+        let scale = self.scale;
+        let size = params.size * scale;
+        let (x, y) = (self.x(params.x) * scale, self.y(params.y) * scale);
+        let fonts = [font];
+        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+        // Without a wrap width, measure the unwrapped text so multi-line alignment still works
+        let box_width = match params.max_width {
+            Some(width) => width.resolve(0.0, self.width as f32) * scale,
+            None => {
+                layout.reset(&LayoutSettings::default());
+                layout.append(&fonts, &TextStyle::new(&params.text, size, 0));
+                // Wrapping is decided on advance widths, so measure those rather than bitmaps
+                layout
+                    .glyphs()
+                    .iter()
+                    .map(|g| {
+                        g.x + font
+                            .metrics_indexed(g.key.glyph_index, g.key.px)
+                            .advance_width
+                    })
+                    .fold(0.0, f32::max)
+                    .ceil()
+            }
+        };
+        let (left, horizontal_align) = match params.align {
+            TextAlign::Left => (x, HorizontalAlign::Left),
+            TextAlign::Center => (x - box_width / 2.0, HorizontalAlign::Center),
+            TextAlign::Right => (x - box_width, HorizontalAlign::Right),
+        };
+        layout.reset(&LayoutSettings {
+            x: left,
+            y,
+            max_width: Some(box_width),
+            horizontal_align,
+            ..LayoutSettings::default()
+        });
+        layout.append(&fonts, &TextStyle::new(&params.text, size, 0));
+        layout
+    }
+    pub fn draw_text(&mut self, params: TextParams, font: &Font) {
+        let paint = self.paint(&params.color);
+        let layout = self.layout_text(&params, font);
+        for glyph in layout.glyphs() {
+            if glyph.width == 0 || glyph.height == 0 {
+                continue;
+            }
+            let (metrics, coverage) = font.rasterize_config(glyph.key);
+            let Some(glyph_size) = IntSize::from_wh(metrics.width as u32, metrics.height as u32)
+            else {
+                continue;
+            };
+            let (Some(mut glyph_pixmap), Some(mask)) = (
+                Pixmap::new(glyph_size.width(), glyph_size.height()),
+                Mask::from_vec(coverage, glyph_size),
+            ) else {
+                continue;
+            };
+            // Glyphs are laid out in device pixels, so move the brush into the glyph's frame
+            let (x, y) = (glyph.x.round(), glyph.y.round());
+            let mut paint = paint.clone();
+            paint
+                .shader
+                .transform(Transform::from_scale(self.scale, self.scale).post_translate(-x, -y));
+            glyph_pixmap.fill_rect(
+                glyph_size.to_int_rect(0, 0).to_rect(),
+                &paint,
+                Transform::identity(),
+                None,
+            );
+            glyph_pixmap.apply_mask(&mask);
+            self.pixmap.draw_pixmap(
+                x as i32 - self.origin.0,
+                y as i32 - self.origin.1,
+                glyph_pixmap.as_ref(),
+                &PixmapPaint::default(),
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+    pub fn draw_path(&mut self, params: PathParams) {
+        let path = match parse_svg_path(&params.d) {
+            Ok(path) => path,
+            Err(e) => {
+                log::error!("Failed to build path: {}", e);
+                return;
+            }
+        };
+        // Path data is in pixels relative to the layer's anchor, while gradients are placed
+        // like other shapes, so they are shifted back
+        let (anchor_x, anchor_y) = (self.x(Coord::default()), self.y(Coord::default()));
+        let transform = self.transform().pre_translate(anchor_x, anchor_y);
+        let unanchor = Transform::from_translate(-anchor_x, -anchor_y);
+        if let Some(fill_color) = &params.fill_color {
+            let mut paint = self.paint(fill_color);
+            paint.shader.transform(unanchor);
+            let fill_rule = match params.fill_rule {
+                PathFillRule::Winding => FillRule::Winding,
+                PathFillRule::EvenOdd => FillRule::EvenOdd,
+            };
+            self.pixmap
+                .fill_path(&path, &paint, fill_rule, transform, None);
+        }
+        if let Some(stroke_color) = &params.stroke_color
+            && params.stroke_width > 0.0
+        {
+            let mut paint = self.paint(stroke_color);
+            paint.shader.transform(unanchor);
+            let stroke = Stroke {
+                width: params.stroke_width,
+                line_cap: match params.line_cap {
+                    PathLineCap::Butt => LineCap::Butt,
+                    PathLineCap::Round => LineCap::Round,
+                    PathLineCap::Square => LineCap::Square,
+                },
+                line_join: match params.line_join {
+                    PathLineJoin::Miter => LineJoin::Miter,
+                    PathLineJoin::Round => LineJoin::Round,
+                    PathLineJoin::Bevel => LineJoin::Bevel,
+                },
+                dash: params
+                    .dash
+                    .and_then(|dash| StrokeDash::new(dash, params.dash_offset)),
+                ..Stroke::default()
+            };
+            self.pixmap
+                .stroke_path(&path, &paint, &stroke, transform, None);
+        }
+    }
+    /// Logical size an image is drawn at.
+    fn image_size(&self, params: &ImageParams, image: &Pixmap) -> (f32, f32) {
+        let width = params
+            .width
+            .map_or(image.width() as f32, |w| w.resolve(0.0, self.width as f32));
+        let height = params.height.map_or(image.height() as f32, |h| {
+            h.resolve(0.0, self.height as f32)
+        });
+        (width, height)
+    }
+    pub fn draw_image(&mut self, params: ImageParams, image: &Pixmap) {
+        let (width, height) = self.image_size(&params, image);
+        if width <= 0.0 || height <= 0.0 {
+            return;
+        }
+        let paint = PixmapPaint {
+            opacity: params.opacity.unwrap_or(1.0).clamp(0.0, 1.0),
+            quality: match params.filter {
+                ImageFilter::Nearest => FilterQuality::Nearest,
+                ImageFilter::Bilinear => FilterQuality::Bilinear,
+                ImageFilter::Bicubic => FilterQuality::Bicubic,
+            },
+            ..PixmapPaint::default()
+        };
+        let transform = self.transform().pre_concat(Transform::from_row(
+            width / image.width() as f32,
+            0.0,
+            0.0,
+            height / image.height() as f32,
+            self.x(params.x),
+            self.y(params.y),
+        ));
+        self.pixmap
+            .draw_pixmap(0, 0, image.as_ref(), &paint, transform, None);
+    }
+}
+fn ltrb_around(x1: f32, y1: f32, x2: f32, y2: f32) -> Option<Rect> {
+    Rect::from_ltrb(x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn square(x: i32) -> DrawOperation {
+        RectangleParams::new(x, 10, x + 10, 20).fill("white").into()
+    }
+    /// A canvas showing layer 1 on a renderer that has drawn it once.
+    fn drawn() -> (CanvasState, Renderer) {
+        let mut canvas = CanvasState::new();
+        let mut renderer = Renderer::new(100, 50);
+        canvas
+            .update(DrawCommand::new().layer(1).push(square(10)).into())
+            .unwrap();
+        canvas.render(&mut renderer, None);
+        canvas.take_events();
+        (canvas, renderer)
+    }
+    fn covers(damage: &[IntRect], x: i32) -> bool {
+        damage
+            .iter()
+            .any(|r| r.contains(&IntRect::from_xywh(x, 10, 10, 10).unwrap()))
+    }
+    #[test]
+    fn clear_removes_layers() {
+        let (mut canvas, mut renderer) = drawn();
+        canvas
+            .update(LayerAction::Clear { layer: 1 }.into())
+            .unwrap();
+        assert!(matches!(
+            canvas.take_events()[..],
+            [Event::LayerCleared { layer: 1 }]
+        ));
+        assert!(covers(&canvas.render(&mut renderer, None), 10));
+        assert!(matches!(
+            canvas.update(LayerAction::Clear { layer: 1 }.into()),
+            Err(CommandError::UnknownLayer(1))
+        ));
+    }
+    #[test]
+    fn append_adds_to_layers() {
+        let (mut canvas, mut renderer) = drawn();
+        let append = LayerAction::Append {
+            layer: 1,
+            operations: vec![square(50)],
+        };
+        canvas.update(append.into()).unwrap();
+        assert!(matches!(
+            canvas.take_events()[..],
+            [Event::LayerReplaced { layer: 1 }]
+        ));
+        assert_eq!(canvas.dump_layer(1).unwrap().operations.len(), 2);
+        assert!(covers(&canvas.render(&mut renderer, None), 50));
+    }
+    #[test]
+    fn append_creates_layers_on_the_default_output() {
+        let mut canvas = CanvasState::new();
+        let config = Config {
+            output: Some("DP-1".to_string()),
+            ..Config::default()
+        };
+        canvas.apply_config(&config, Theme::default());
+        let append = LayerAction::Append {
+            layer: 2,
+            operations: vec![square(10)],
+        };
+        canvas.update(append.into()).unwrap();
+        let summaries = canvas.layer_summaries();
+        assert_eq!(summaries.len(), 1);
+        assert!(summaries[0].output == OutputTarget::Named("DP-1".to_string()));
+        let mut renderer = Renderer::new(100, 50);
+        assert!(covers(&canvas.render(&mut renderer, Some("DP-1")), 10));
+        let mut other = Renderer::new(100, 50);
+        canvas.render(&mut other, Some("HDMI-A-1"));
+        assert!(other.pixmap.data().iter().all(|&byte| byte == 0));
+    }
+    #[test]
+    fn hide_and_show_redraw_layers() {
+        let (mut canvas, mut renderer) = drawn();
+        canvas
+            .update(LayerAction::Hide { layer: 1 }.into())
+            .unwrap();
+        assert!(covers(&canvas.render(&mut renderer, None), 10));
+        assert!(canvas.render(&mut renderer, None).is_empty());
+        canvas
+            .update(LayerAction::Show { layer: 1 }.into())
+            .unwrap();
+        assert!(covers(&canvas.render(&mut renderer, None), 10));
+        assert!(matches!(
+            canvas.update(LayerAction::Hide { layer: 7 }.into()),
+            Err(CommandError::UnknownLayer(7))
+        ));
+    }
+    #[test]
+    fn set_timeout_redraws_layers() {
+        let (mut canvas, mut renderer) = drawn();
+        let timeout = LayerAction::SetTimeout {
+            layer: 1,
+            ms: Some(60_000),
+        };
+        canvas.update(timeout.into()).unwrap();
+        assert!(covers(&canvas.render(&mut renderer, None), 10));
+        assert!(canvas.layer_summaries()[0].remaining_ms.is_some());
+    }
 }
@@ -0,0 +1,90 @@
+use crate::config::{read_asset, resolve_asset};
+use fontdb::{Database, Family, Query};
+use fontdue::{Font, FontSettings};
+use std::collections::HashMap;
+use std::path::PathBuf;
+pub const FALLBACK_FONT: &[u8] = include_bytes!("../assets/Cantarell-Regular.ttf");
+/// Largest font file read from `fonts_dir`.
+const MAX_FONT_FILE_SIZE: u64 = 64 << 20;
+pub struct FontCache {
+    database: Option<Database>,
+    fonts: HashMap<String, Option<Font>>,
+    fallback: Font,
+    /// The configured `fonts_dir`, the only place font files are read from.
+    dir: Option<PathBuf>,
+}
+impl FontCache {
+    pub fn new() -> Self {
+        let fallback = Font::from_bytes(FALLBACK_FONT, FontSettings::default())
+            .expect("Failed to load bundled fallback font");
+        Self {
+            database: None,
+            fonts: HashMap::new(),
+            fallback,
+            dir: None,
+        }
+    }
+    /// Changes where font files are read from, forgetting the ones read so far.
+    pub fn set_dir(&mut self, dir: Option<PathBuf>) {
+        if self.dir != dir {
+            self.dir = dir;
+            self.fonts.clear();
+        }
+    }
+    /// Resolves a font family name or a font file path, falling back to the bundled font.
+    pub fn get(&mut self, name: Option<&str>) -> &Font {
+        let Some(name) = name else {
+            return &self.fallback;
+        };
+        if !self.fonts.contains_key(name) {
+            let font = self.load(name);
+            if font.is_none() {
+                log::warn!("Font '{}' not found, using fallback", name);
+            }
+            self.fonts.insert(name.to_string(), font);
+        }
+        match self.fonts.get(name) {
+            Some(Some(font)) => font,
+            _ => &self.fallback,
+        }
+    }
+    fn load(&mut self, name: &str) -> Option<Font> {
+        if name.contains('/') {
+            return self.load_file(name);
+        }
+        let database = self.database.get_or_insert_with(|| {
+            let mut database = Database::new();
+            database.load_system_fonts();
+            log::info!("Loaded {} system font faces", database.len());
+            database
+        });
+        let families = [Family::Name(name)];
+        let id = database.query(&Query {
+            families: &families,
+            ..Query::default()
+        })?;
+        database.with_face_data(id, |data, index| {
+            Self::from_bytes(data.to_vec(), index)
+                .map_err(|e| log::error!("Failed to parse font '{}': {}", name, e))
+                .ok()
+        })?
+    }
+    fn load_file(&self, name: &str) -> Option<Font> {
+        let data = resolve_asset(self.dir.as_deref(), name, "fonts_dir")
+            .and_then(|path| read_asset(&path, MAX_FONT_FILE_SIZE))
+            .map_err(|e| log::error!("Failed to read font: {:#}", e))
+            .ok()?;
+        Self::from_bytes(data, 0)
+            .map_err(|e| log::error!("Failed to parse font {}: {}", name, e))
+            .ok()
+    }
+    fn from_bytes(data: Vec<u8>, index: u32) -> Result<Font, &'static str> {
+        Font::from_bytes(
+            data,
+            FontSettings {
+                collection_index: index,
+                ..FontSettings::default()
+            },
+        )
+    }
+}
@@ -0,0 +1,357 @@
+use crate::config::{Config, ConfigSource};
+use crate::draw::{CanvasState, Renderer};
+use crate::network::{CommandError, Request};
+use crate::widget::Theme;
+use anyhow::{Context, Result};
+use calloop::channel::{Channel, Event};
+use calloop::signals::Signals;
+use calloop::timer::{TimeoutAction, Timer};
+use calloop::{EventLoop, LoopHandle, RegistrationToken};
+use overlay_protocol::command::{Command, Query, parse_command};
+use overlay_protocol::response::{Event as OverlayEvent, RenderStats, Response, SurfaceInfo};
+use serde::de::IgnoredAny;
+use std::io::Read;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+/// Surface size for `--headless`, written as `WIDTHxHEIGHT`.
+#[derive(Clone, Copy)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+impl std::str::FromStr for Size {
+    type Err = String;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid size '{}', expected WIDTHxHEIGHT", text);
+        let (width, height) = text.split_once('x').ok_or_else(invalid)?;
+        let width = width.parse().map_err(|_| invalid())?;
+        let height = height.parse().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        Ok(Size { width, height })
+    }
+}
+/// Renders the canvas into an offscreen pixmap instead of Wayland surfaces, so the
+/// overlay can run without a compositor.
+pub struct HeadlessApp {
+    canvas: CanvasState,
+    renderer: Renderer,
+    snapshot: Option<String>,
+    requests: u64,
+    frames_rendered: u64,
+    last_render: Option<(Instant, Duration)>,
+    events: broadcast::Sender<OverlayEvent>,
+    handle: Option<LoopHandle<'static, Self>>,
+    /// Wakes the loop when a layer next expires, so subscribers hear about it promptly.
+    timer: Option<RegistrationToken>,
+}
+impl HeadlessApp {
+    pub fn new(
+        size: Size,
+        scale: f32,
+        snapshot: Option<String>,
+        events: broadcast::Sender<OverlayEvent>,
+    ) -> Self {
+        Self {
+            canvas: CanvasState::new(),
+            renderer: Renderer::with_scale(size.width, size.height, scale),
+            snapshot,
+            requests: 0,
+            frames_rendered: 0,
+            last_render: None,
+            events,
+            handle: None,
+            timer: None,
+        }
+    }
+    pub fn apply_config(&mut self, config: &Config, theme: Theme) {
+        self.canvas.apply_config(config, theme);
+    }
+    /// Applies every command in a file (`-` for stdin), then writes the snapshot.
+    /// Commands are JSON values one after another, usually one per line.
+    pub fn run_file(&mut self, path: &str) -> Result<()> {
+        let mut text = String::new();
+        if path == "-" {
+            std::io::stdin().read_to_string(&mut text)?;
+        } else {
+            text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path))?;
+        }
+        let mut values = serde_json::Deserializer::from_str(&text).into_iter::<IgnoredAny>();
+        let mut start = 0;
+        while let Some(value) = values.next() {
+            value.with_context(|| format!("{}: invalid JSON", path))?;
+            let end = values.byte_offset();
+            let json = &text[start..end];
+            let line = text[..start].lines().count() + 1;
+            let command = parse_command(json.trim_start())
+                .with_context(|| format!("{}: invalid command near line {}", path, line))?;
+            log::info!("Applying {} command", command.name());
+            self.handle(command)
+                .with_context(|| format!("{}: command near line {} failed", path, line))?;
+            start = end;
+        }
+        self.handle(Command::Query(Query::Snapshot { output: None }))?;
+        Ok(())
+    }
+    /// Answers requests from the network listeners until they shut down, re-reading the
+    /// config from `source` on SIGHUP.
+    pub fn serve(&mut self, receiver: Channel<Request>, signals: Signals, source: ConfigSource) {
+        let mut event_loop: EventLoop<Self> = EventLoop::try_new().unwrap();
+        let signal = event_loop.get_signal();
+        self.handle = Some(event_loop.handle());
+        event_loop
+            .handle()
+            .insert_source(receiver, move |event, _, app| match event {
+                Event::Msg(request) => app.handle_request(request),
+                Event::Closed => signal.stop(),
+            })
+            .expect("Failed to insert command channel");
+        event_loop
+            .handle()
+            .insert_source(signals, move |_, _, app| match source.load() {
+                Ok((config, theme)) => {
+                    log::info!("Reloaded config");
+                    app.apply_config(&config, theme);
+                }
+                Err(e) => log::error!("Failed to reload config: {:#}", e),
+            })
+            .expect("Failed to insert signal source");
+        event_loop
+            .run(None, self, |_| {})
+            .expect("Failed to run event loop");
+    }
+    fn handle_request(&mut self, request: Request) {
+        log::info!("Received {} command", request.command.name());
+        let response = self.handle(request.command).unwrap_or_else(|e| {
+            log::error!("Failed to apply command: {}", e);
+            Response::from(e)
+        });
+        self.expire();
+        if let Some(reply) = request.reply {
+            let _ = reply.send(response);
+        }
+    }
+    /// Removes expired layers, sends the pending events and arms the timer for the next
+    /// expiry.
+    fn expire(&mut self) {
+        self.canvas.prune();
+        for event in self.canvas.take_events() {
+            let _ = self.events.send(event);
+        }
+        let Some(handle) = &self.handle else {
+            return;
+        };
+        if let Some(token) = self.timer.take() {
+            handle.remove(token);
+        }
+        if let Some(deadline) = self.canvas.next_deadline(Instant::now()) {
+            let timer = Timer::from_deadline(deadline);
+            match handle.insert_source(timer, |_, _, app| {
+                app.timer = None;
+                app.expire();
+                TimeoutAction::Drop
+            }) {
+                Ok(token) => self.timer = Some(token),
+                Err(e) => log::error!("Failed to arm timer: {}", e),
+            }
+        }
+    }
+    fn handle(&mut self, command: Command) -> Result<Response, CommandError> {
+        self.requests += 1;
+        self.canvas.prune();
+        let query = match command {
+            Command::Query(query) => query,
+            command => return self.canvas.update(command).map(|()| Response::ok()),
+        };
+        Ok(match query {
+            Query::ListLayers => Response::data(self.canvas.layer_summaries()),
+            Query::DumpLayer { layer } => Response::data(self.canvas.dump_layer(layer)?),
+            Query::Surfaces => Response::data([SurfaceInfo {
+                output: None,
+                width: self.renderer.width,
+                height: self.renderer.height,
+                scale: self.renderer.scale,
+                configured: true,
+            }]),
+            Query::Stats => Response::data(RenderStats::new(
+                self.requests,
+                self.frames_rendered,
+                self.last_render,
+            )),
+            Query::Snapshot { output } => {
+                let path = self.snapshot.clone().ok_or_else(|| {
+                    CommandError::Snapshot("the overlay was started without --snapshot".to_string())
+                })?;
+                let started = Instant::now();
+                self.canvas.render(&mut self.renderer, output.as_deref());
+                self.frames_rendered += 1;
+                self.last_render = Some((started, started.elapsed()));
+                Response::data(self.renderer.save_png(&path)?)
+            }
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use overlay_protocol::color::{Gradient, GradientStop};
+    use overlay_protocol::command::DrawCommand;
+    use overlay_protocol::coord::Coord;
+    use overlay_protocol::draw::{
+        CircleParams, ClickableParams, DrawOperation, ImageParams, LineParams, PathParams,
+        PixelParams, RectangleParams, TextParams,
+    };
+    use std::path::{Path, PathBuf};
+    use tiny_skia::{Color, Pixmap};
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+    const FONT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/Cantarell-Regular.ttf");
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("overlay-{}-{}.png", std::process::id(), name))
+    }
+    /// Draws `operation` headless and compares the snapshot with `tests/golden/<name>.png`.
+    /// Run with `UPDATE_GOLDEN=1` to write the golden image instead.
+    fn assert_renders(name: &str, operation: impl Into<DrawOperation>) {
+        let path = temp_path(name);
+        let (events, _) = broadcast::channel(1);
+        let size = Size {
+            width: 96,
+            height: 48,
+        };
+        let snapshot = path.to_string_lossy().into_owned();
+        let mut app = HeadlessApp::new(size, 1.0, Some(snapshot), events);
+        let config = Config {
+            fonts_dir: Path::new(FONT).parent().map(Path::to_path_buf),
+            images_dir: Some(std::env::temp_dir()),
+            ..Config::default()
+        };
+        app.apply_config(&config, Theme::default());
+        app.handle(DrawCommand::new().push(operation).into())
+            .unwrap();
+        app.handle(Command::Query(Query::Snapshot { output: None }))
+            .unwrap();
+        let actual = Pixmap::load_png(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let golden = Path::new(GOLDEN_DIR).join(format!("{}.png", name));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            actual.save_png(&golden).unwrap();
+            return;
+        }
+        let expected =
+            Pixmap::load_png(&golden).unwrap_or_else(|e| panic!("{}: {}", golden.display(), e));
+        assert_eq!(
+            (actual.width(), actual.height()),
+            (expected.width(), expected.height())
+        );
+        // Allow for rounding differences between platforms
+        let differing = actual
+            .data()
+            .iter()
+            .zip(expected.data())
+            .filter(|(a, b)| a.abs_diff(**b) > 2)
+            .count();
+        assert_eq!(differing, 0, "{} differs from {}", name, golden.display());
+    }
+    #[test]
+    fn pixel() {
+        assert_renders("pixel", PixelParams::new(10, 10, "0xFFFF0000"));
+    }
+    #[test]
+    fn line() {
+        assert_renders(
+            "line",
+            LineParams::new(4, 4, 90, 40).width(3.0).color("white"),
+        );
+    }
+    #[test]
+    fn circle() {
+        assert_renders(
+            "circle",
+            CircleParams::new(48, 24, 18.0)
+                .fill("#3080ff")
+                .outline(2.0, "white"),
+        );
+    }
+    #[test]
+    fn rectangle() {
+        assert_renders(
+            "rectangle",
+            RectangleParams::new(8, 8, 88, 40)
+                .fill("rgba(255, 0, 0, 0.5)")
+                .outline(3.0, "0xFF00FF00"),
+        );
+    }
+    #[test]
+    fn rectangle_gradient() {
+        let gradient = Gradient::Linear {
+            x1: Coord::from(8),
+            y1: Coord::from(0),
+            x2: Coord::from(88),
+            y2: Coord::from(0),
+            stops: vec![
+                GradientStop::new(0.0, "red"),
+                GradientStop::new(1.0, "blue"),
+            ],
+        };
+        assert_renders(
+            "rectangle_gradient",
+            RectangleParams::new(8, 8, 88, 40).fill(gradient),
+        );
+    }
+    #[test]
+    fn text() {
+        // A multi-word label must stay on one line
+        assert_renders(
+            "text",
+            TextParams::new(4, 12, "Volume 50%")
+                .font(FONT)
+                .size(14.0)
+                .color("white"),
+        );
+    }
+    #[test]
+    fn text_gradient() {
+        let gradient = Gradient::Linear {
+            x1: Coord::from(4),
+            y1: Coord::from(0),
+            x2: Coord::from(80),
+            y2: Coord::from(0),
+            stops: vec![
+                GradientStop::new(0.0, "red"),
+                GradientStop::new(1.0, "blue"),
+            ],
+        };
+        assert_renders(
+            "text_gradient",
+            TextParams::new(4, 12, "Volume 50%")
+                .font(FONT)
+                .size(14.0)
+                .color(gradient),
+        );
+    }
+    #[test]
+    fn path() {
+        assert_renders(
+            "path",
+            PathParams::new("M 10 40 L 48 6 L 86 40 Z")
+                .fill("orange")
+                .stroke(2.0, "black"),
+        );
+    }
+    #[test]
+    fn image() {
+        let source = temp_path("image-source");
+        let mut pixmap = Pixmap::new(4, 4).unwrap();
+        pixmap.fill(Color::from_rgba8(0, 200, 100, 255));
+        pixmap.save_png(&source).unwrap();
+        let image = ImageParams::file(source.to_string_lossy(), 16, 8).size(32, 32);
+        assert_renders("image", image);
+        let _ = std::fs::remove_file(&source);
+    }
+    #[test]
+    fn clickable() {
+        // Clickable regions take input but draw nothing
+        assert_renders("clickable", ClickableParams::new("button", 0, 0, 96, 48));
+    }
+}
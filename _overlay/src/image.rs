@@ -0,0 +1,173 @@
+use crate::config::{read_asset, resolve_asset};
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::SystemTime;
+use tiny_skia::{ColorU8, Pixmap};
+const MAX_CACHED_IMAGES: usize = 32;
+/// Largest image file read from `images_dir`.
+const MAX_IMAGE_FILE_SIZE: u64 = 16 << 20;
+/// Largest image decoded, in pixels.
+const MAX_IMAGE_PIXELS: u64 = 4096 * 4096;
+const PNG_MAGIC: &[u8] = b"\x89PNG";
+const QOI_MAGIC: &[u8] = b"qoif";
+struct CachedImage {
+    modified: Option<SystemTime>,
+    pixmap: Rc<Pixmap>,
+}
+/// Decoded images keyed by file path or by a hash of their inline payload.
+pub struct ImageCache {
+    images: HashMap<String, CachedImage>,
+    order: VecDeque<String>,
+    /// The configured `images_dir`, the only place image files are read from.
+    dir: Option<PathBuf>,
+}
+impl ImageCache {
+    pub fn new() -> Self {
+        Self {
+            images: HashMap::new(),
+            order: VecDeque::new(),
+            dir: None,
+        }
+    }
+    /// Changes where image files are read from, forgetting the ones read so far.
+    pub fn set_dir(&mut self, dir: Option<PathBuf>) {
+        if self.dir != dir {
+            self.dir = dir;
+            self.images.retain(|_, image| image.modified.is_none());
+            self.order.retain(|key| !key.starts_with("path:"));
+        }
+    }
+    pub fn load_path(&mut self, path: &str) -> Result<Rc<Pixmap>> {
+        let path = resolve_asset(self.dir.as_deref(), path, "images_dir")?;
+        let modified = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("failed to stat {}", path.display()))?;
+        let key = format!("path:{}", path.display());
+        if let Some(cached) = self.images.get(&key)
+            && cached.modified == Some(modified)
+        {
+            return Ok(cached.pixmap.clone());
+        }
+        let bytes = read_asset(&path, MAX_IMAGE_FILE_SIZE)?;
+        let pixmap = Rc::new(decode(&bytes)?);
+        self.insert(key, Some(modified), pixmap.clone());
+        Ok(pixmap)
+    }
+    pub fn load_base64(&mut self, data: &str) -> Result<Rc<Pixmap>> {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let key = format!("data:{:016x}", hasher.finish());
+        if let Some(cached) = self.images.get(&key) {
+            return Ok(cached.pixmap.clone());
+        }
+        let bytes = STANDARD
+            .decode(data.trim())
+            .context("invalid base64 image data")?;
+        let pixmap = Rc::new(decode(&bytes)?);
+        self.insert(key, None, pixmap.clone());
+        Ok(pixmap)
+    }
+    fn insert(&mut self, key: String, modified: Option<SystemTime>, pixmap: Rc<Pixmap>) {
+        if self
+            .images
+            .insert(key.clone(), CachedImage { modified, pixmap })
+            .is_none()
+        {
+            self.order.push_back(key);
+        }
+        while self.order.len() > MAX_CACHED_IMAGES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.images.remove(&oldest);
+            }
+        }
+    }
+}
+fn decode(bytes: &[u8]) -> Result<Pixmap> {
+    if bytes.starts_with(PNG_MAGIC) {
+        // The size is in the IHDR chunk, which must come first
+        let size = |at: usize| {
+            bytes
+                .get(at..at + 4)
+                .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        let (width, height) = size(16).zip(size(20)).context("truncated PNG header")?;
+        check_size(width, height)?;
+        Pixmap::decode_png(bytes).context("failed to decode PNG")
+    } else if bytes.starts_with(QOI_MAGIC) {
+        decode_qoi(bytes)
+    } else {
+        Err(anyhow!("unsupported image format, expected PNG or QOI"))
+    }
+}
+fn decode_qoi(bytes: &[u8]) -> Result<Pixmap> {
+    let mut decoder = qoi::Decoder::new(bytes)
+        .context("failed to decode QOI header")?
+        .with_channels(qoi::Channels::Rgba);
+    let header = *decoder.header();
+    check_size(header.width, header.height)?;
+    let rgba = decoder.decode_to_vec().context("failed to decode QOI")?;
+    let mut pixmap = Pixmap::new(header.width, header.height)
+        .ok_or_else(|| anyhow!("invalid image size {}x{}", header.width, header.height))?;
+    for (pixel, rgba) in pixmap.pixels_mut().iter_mut().zip(rgba.chunks_exact(4)) {
+        *pixel = ColorU8::from_rgba(rgba[0], rgba[1], rgba[2], rgba[3]).premultiply();
+    }
+    Ok(pixmap)
+}
+fn check_size(width: u32, height: u32) -> Result<()> {
+    if width as u64 * height as u64 > MAX_IMAGE_PIXELS {
+        return Err(anyhow!(
+            "image of {}x{} exceeds the maximum of {} pixels",
+            width,
+            height,
+            MAX_IMAGE_PIXELS
+        ));
+    }
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn header(magic: &[u8], at: usize, width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.resize(at, 0);
+        bytes.extend(width.to_be_bytes());
+        bytes.extend(height.to_be_bytes());
+        bytes.extend([4, 0, 0, 0, 0]);
+        bytes
+    }
+    #[test]
+    fn refuses_huge_images_before_decoding() {
+        let png = header(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR", 16, 8192, 8192);
+        let qoi = header(QOI_MAGIC, 4, 8192, 8192);
+        for bytes in [png, qoi] {
+            let e = decode(&bytes).unwrap_err();
+            assert!(e.to_string().contains("8192x8192 exceeds"), "{:#}", e);
+        }
+        let data = STANDARD.encode(header(QOI_MAGIC, 4, 4096, 4097));
+        let e = ImageCache::new().load_base64(&data).unwrap_err();
+        assert!(e.to_string().contains("4096x4097 exceeds"), "{:#}", e);
+    }
+    #[test]
+    fn reads_files_from_images_dir_only() {
+        let dir = std::env::temp_dir().join(format!("overlay-{}-images", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut pixmap = Pixmap::new(2, 2).unwrap();
+        pixmap.fill(tiny_skia::Color::WHITE);
+        pixmap.save_png(dir.join("icon.png")).unwrap();
+        let mut images = ImageCache::new();
+        let undirected = images.load_path("icon.png");
+        images.set_dir(Some(dir.clone()));
+        let inside = images.load_path("icon.png");
+        let outside = images.load_path("../../etc/passwd");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(undirected.unwrap_err().to_string().contains("images_dir"));
+        assert_eq!(inside.unwrap().width(), 2);
+        assert!(outside.is_err());
+    }
+}
@@ -1,67 +1,370 @@
+mod animation;
+mod color;
+mod config;
+mod damage;
 mod draw;
+mod font;
+mod headless;
+mod image;
 mod network;
+mod path;
+mod tls;
 mod wayland;
+mod widget;
+use crate::config::{Config, ConfigSource};
 use crate::draw::CanvasState;
-use crate::network::Command;
+use crate::headless::{HeadlessApp, Size};
+use crate::network::{CommandError, Request, TcpListener, UdpListener};
+use crate::tls::load_acceptor;
 use crate::wayland::WaylandApp;
-use calloop::EventLoop;
+use calloop::channel::Event as ChannelEvent;
+use calloop::signals::{Signal, Signals};
+use calloop::timer::{TimeoutAction, Timer};
+use calloop::{EventLoop, LoopHandle, RegistrationToken};
 use calloop_wayland_source::WaylandSource;
 use clap::Parser;
-use crossbeam::channel::Receiver;
+use overlay_protocol::auth::load_secret;
+use overlay_protocol::command::{Command, Query};
+use overlay_protocol::config::TlsFiles;
+use overlay_protocol::frame::DEFAULT_MAX_FRAME_SIZE;
+use overlay_protocol::response::{Event, RenderStats, Response};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use tokio::runtime::Builder as RuntimeBuilder;
+use tokio::sync::broadcast;
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Args {
     #[arg(long)]
     udp: Option<u16>,
+    /// Address for the UDP listener [default: 127.0.0.1]
+    #[arg(long)]
+    bind: Option<IpAddr>,
+    /// File with a shared secret; UDP commands without a matching, recent HMAC are rejected.
+    #[arg(long)]
+    udp_secret_file: Option<PathBuf>,
     #[arg(long)]
     socket: Option<String>,
+    /// Address for a TCP listener speaking the same protocol as the Unix socket.
+    #[arg(long, value_name = "ADDR:PORT")]
+    tcp: Option<SocketAddr>,
+    /// PEM certificate chain; the TCP listener requires TLS when it's given. Each TLS flag
+    /// overrides the same setting in the config.
+    #[arg(long)]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-cert.
+    #[arg(long)]
+    tls_key: Option<PathBuf>,
+    /// PEM CA certificate; TCP clients must present a certificate it issued.
+    #[arg(long)]
+    tls_client_ca: Option<PathBuf>,
+    /// Allow a TCP listener on a non-loopback address without TLS client certificates.
+    #[arg(long)]
+    insecure: bool,
+    /// Largest command accepted on a socket connection, in bytes [default: 16 MiB]
+    #[arg(long)]
+    max_frame_size: Option<usize>,
+    /// Render offscreen at WIDTHxHEIGHT instead of onto Wayland outputs.
+    #[arg(long, value_name = "WxH")]
+    headless: Option<Size>,
+    /// Device pixels per logical pixel in headless mode.
+    #[arg(long, default_value_t = 1.0, requires = "headless")]
+    scale: f32,
+    /// PNG file the Snapshot command writes to; clients can't choose another.
+    #[arg(long)]
+    snapshot: Option<String>,
+    /// Apply the commands in this file (`-` for stdin), write the snapshot and exit.
+    #[arg(long, requires_all = ["headless", "snapshot"])]
+    commands: Option<String>,
+    /// JSON file with styling defaults for widget commands.
+    #[arg(long)]
+    theme: Option<PathBuf>,
+    /// Config file, by default `$XDG_CONFIG_HOME/overlay/config.toml`.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Print the JSON schema of the commands and exit.
+    #[arg(long)]
+    schema: bool,
 }
+/// Fallback redraw interval for animations on surfaces that won't send frame callbacks.
+const ANIMATION_INTERVAL: Duration = Duration::from_millis(16);
 struct AppData {
     canvas: CanvasState,
-    receiver: Receiver<Command>,
     app: WaylandApp,
+    /// Delivers events to subscribed socket clients.
+    events: broadcast::Sender<Event>,
+    handle: LoopHandle<'static, AppData>,
+    source: ConfigSource,
+    /// Wakes the loop when a layer next expires or finishes fading out.
+    timer: Option<RegistrationToken>,
+    snapshot: Option<String>,
     frame_count: u64,
-    dirty: bool,
+    frames_rendered: u64,
+    /// When the last frame was drawn and how long drawing it took.
+    last_render: Option<(Instant, Duration)>,
+}
+impl AppData {
+    fn handle_request(&mut self, request: Request) {
+        log::info!("Received {} command", request.command.name());
+        let result = match request.command {
+            Command::Query(query) => self.query(query),
+            command => {
+                self.app.invalidate();
+                self.canvas.update(command).map(|()| Response::ok())
+            }
+        };
+        let response = result.unwrap_or_else(|e| {
+            log::error!("Failed to apply command: {}", e);
+            Response::from(e)
+        });
+        if let Some(reply) = request.reply {
+            let _ = reply.send(response);
+        }
+    }
+    /// Draws what changed onto surfaces ready for a new frame, then arms the timer for the
+    /// next moment the canvas changes on its own.
+    fn redraw(&mut self) {
+        self.frame_count += 1;
+        if self.frame_count.is_multiple_of(60) {
+            log::debug!(
+                "Frame {}: {} surfaces, {} configured",
+                self.frame_count,
+                self.app.surfaces.len(),
+                self.app.surfaces.iter().filter(|s| s.configured).count()
+            );
+        }
+        let animating = self.canvas.is_animating();
+        if self.canvas.prune() || animating {
+            self.app.invalidate();
+        }
+        let events = self.canvas.take_events();
+        for event in events.into_iter().chain(self.app.take_events(&self.canvas)) {
+            // Fails only when nobody is subscribed
+            let _ = self.events.send(event);
+        }
+        self.app.update_input_regions(&self.canvas);
+        let started = Instant::now();
+        let rendered = self.app.render(&mut self.canvas);
+        if rendered > 0 {
+            self.frames_rendered += rendered as u64;
+            self.last_render = Some((started, started.elapsed()));
+        }
+        let now = Instant::now();
+        let mut deadline = self.canvas.next_deadline(now);
+        // Animations advance on frame callbacks, unless no surface is waiting for one
+        if animating && !self.app.frame_pending() {
+            let next_frame = now + ANIMATION_INTERVAL;
+            deadline = Some(deadline.map_or(next_frame, |d| d.min(next_frame)));
+        }
+        if let Some(token) = self.timer.take() {
+            self.handle.remove(token);
+        }
+        if let Some(deadline) = deadline {
+            let timer = Timer::from_deadline(deadline);
+            match self.handle.insert_source(timer, |_, _, data| {
+                data.timer = None;
+                data.redraw();
+                TimeoutAction::Drop
+            }) {
+                Ok(token) => self.timer = Some(token),
+                Err(e) => log::error!("Failed to arm timer: {}", e),
+            }
+        }
+    }
+    /// Re-reads the config. Listener settings only change on restart.
+    fn reload(&mut self) {
+        match self.source.load() {
+            Ok((config, theme)) => {
+                log::info!("Reloaded config");
+                self.canvas.apply_config(&config, theme);
+                self.app.apply_config(&config);
+                self.app.invalidate();
+                self.redraw();
+            }
+            Err(e) => log::error!("Failed to reload config: {:#}", e),
+        }
+    }
+    fn query(&self, query: Query) -> Result<Response, CommandError> {
+        Ok(match query {
+            Query::ListLayers => Response::data(self.canvas.layer_summaries()),
+            Query::DumpLayer { layer } => Response::data(self.canvas.dump_layer(layer)?),
+            Query::Surfaces => Response::data(self.app.surface_info()),
+            Query::Stats => Response::data(RenderStats::new(
+                self.frame_count,
+                self.frames_rendered,
+                self.last_render,
+            )),
+            Query::Snapshot { output } => {
+                let path = self.snapshot.clone().ok_or_else(|| {
+                    CommandError::Snapshot("the overlay was started without --snapshot".to_string())
+                })?;
+                let surface = self
+                    .app
+                    .surfaces
+                    .iter()
+                    .filter(|s| s.configured)
+                    .find(|s| output.is_none() || s.output_name == output)
+                    .ok_or_else(|| CommandError::Snapshot("no matching surface".to_string()))?;
+                Response::data(surface.renderer.save_png(&path)?)
+            }
+        })
+    }
+}
+/// TLS files for the TCP listener, with each flag overriding the config's setting.
+fn tls_files(args: &Args, config: &Config) -> TlsFiles {
+    TlsFiles {
+        cert: args.tls_cert.clone(),
+        key: args.tls_key.clone(),
+        client_ca: args.tls_client_ca.clone(),
+    }
+    .or(config.listeners.tls.clone())
 }
 fn main() {
     env_logger::init();
     let args = Args::parse();
-    if args.udp.is_none() && args.socket.is_none() {
-        eprintln!("Error: You must provide at least one of --udp or --socket");
+    if args.schema {
+        let schema = overlay_protocol::schema::command_schema();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema).unwrap_or_default()
+        );
+        return;
+    }
+    let source = ConfigSource {
+        path: args.config.clone(),
+        theme: args.theme.clone(),
+    };
+    let (config, theme) = match source.load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let secret_file = args
+        .udp_secret_file
+        .as_ref()
+        .or(config.listeners.udp_secret_file.as_ref());
+    let secret = match secret_file.map(|path| load_secret(path)).transpose() {
+        Ok(secret) => secret,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let udp = args.udp.or(config.listeners.udp).map(|port| UdpListener {
+        bind: args
+            .bind
+            .or(config.listeners.bind)
+            .unwrap_or(Ipv4Addr::LOCALHOST.into()),
+        port,
+        secret,
+    });
+    let tls = tls_files(&args, &config);
+    let tcp = args.tcp.or(config.listeners.tcp);
+    if let Some(addr) = tcp
+        && !addr.ip().is_loopback()
+        && (tls.cert.is_none() || tls.client_ca.is_none())
+        && !(args.insecure || config.insecure)
+    {
+        eprintln!(
+            "Error: Refusing to listen for TCP on {} without TLS and client certificates, \
+             set --tls-cert and --tls-client-ca or pass --insecure",
+            addr
+        );
         std::process::exit(1);
     }
-    log::info!("Starting wayland overlay server");
-    let (mut app, event_queue, conn) = WaylandApp::new(1920, 1080);
-    let qh = event_queue.handle();
-    app.create_layer_surface(&qh);
-    log::info!("Layer surface created");
-    let (sender, receiver) = crossbeam::channel::unbounded();
-    let canvas = CanvasState::new();
+    let tls = match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => load_acceptor(cert, key, tls.client_ca.as_deref()).map(Some),
+        (None, None) if tls.client_ca.is_none() => Ok(None),
+        _ => Err(anyhow::anyhow!("TLS needs both a certificate and a key")),
+    };
+    let tls = match tls {
+        Ok(tls) => tls,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let tcp = tcp.map(|addr| TcpListener { addr, tls });
+    let socket = args.socket.clone().or(config.listeners.socket.clone());
+    let max_frame_size = args
+        .max_frame_size
+        .or(config.max_frame_size)
+        .unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+    if udp.is_none() && tcp.is_none() && socket.is_none() && args.commands.is_none() {
+        eprintln!("Error: You must provide at least one of --udp, --tcp, --socket or --commands");
+        std::process::exit(1);
+    }
+    // Blocks SIGHUP on this thread, so create it before the runtime threads that inherit the mask
+    let signals = Signals::new(&[Signal::SIGHUP]).expect("Failed to watch SIGHUP");
+    let (sender, receiver) = calloop::channel::channel();
+    let (events, _) = broadcast::channel(64);
     let rt = RuntimeBuilder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap();
-    let udp = args.udp;
-    let socket = args.socket.clone();
-    log::info!(
-        "Starting network listeners - UDP: {:?}, Socket: {:?}",
-        udp,
-        socket
-    );
-    rt.spawn(async move {
-        if let Err(e) = crate::network::start_listeners(udp, socket.as_deref(), sender).await {
-            eprintln!("Network error: {}", e);
+    if udp.is_some() || tcp.is_some() || socket.is_some() {
+        log::info!(
+            "Starting network listeners - UDP: {:?}, TCP: {:?}, Socket: {:?}",
+            udp.as_ref().map(|udp| udp.port),
+            tcp.as_ref().map(|tcp| tcp.addr),
+            socket
+        );
+        let events = events.clone();
+        rt.spawn(async move {
+            let listeners = crate::network::start_listeners(
+                udp,
+                tcp,
+                socket.as_deref(),
+                max_frame_size,
+                sender,
+                events,
+            );
+            if let Err(e) = listeners.await {
+                eprintln!("Network error: {}", e);
+            }
+        });
+    }
+    if let Some(size) = args.headless {
+        log::info!(
+            "Starting headless overlay at {}x{}",
+            size.width,
+            size.height
+        );
+        let mut app = HeadlessApp::new(size, args.scale, args.snapshot, events);
+        app.apply_config(&config, theme);
+        match args.commands {
+            Some(path) => {
+                if let Err(e) = app.run_file(&path) {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+            None => app.serve(receiver, signals, source),
         }
-    });
+        return;
+    }
+    log::info!("Starting wayland overlay server");
+    let (mut app, event_queue, conn) = WaylandApp::new();
+    app.apply_config(&config);
+    let mut canvas = CanvasState::new();
+    canvas.apply_config(&config, theme);
+    rt.spawn(canvas.focused_output.clone().track());
+    let mut event_loop: EventLoop<AppData> = EventLoop::try_new().unwrap();
     let mut data = AppData {
         canvas,
-        receiver,
         app,
+        events,
+        handle: event_loop.handle(),
+        source,
+        timer: None,
+        snapshot: args.snapshot,
         frame_count: 0,
-        dirty: false,
+        frames_rendered: 0,
+        last_render: None,
     };
-    let mut event_loop: EventLoop<AppData> = EventLoop::try_new().unwrap();
     let wayland_source = WaylandSource::new(conn, event_queue);
     event_loop
         .handle()
@@ -70,52 +373,55 @@ fn main() {
                 log::error!("Wayland dispatch error: {}", e);
                 e
             })?;
+            // Configures and frame callbacks may have made surfaces ready to draw
+            data.redraw();
             Ok(count)
         })
         .expect("Failed to insert wayland source");
-    log::info!("Starting main event loop");
-    let timer = calloop::timer::Timer::from_duration(std::time::Duration::from_millis(16));
     event_loop
         .handle()
-        .insert_source(timer, |_, _, data| {
-            if data.frame_count % 60 == 0 {
-                log::debug!(
-                    "Frame {}: configured={}, width={}, height={}",
-                    data.frame_count,
-                    data.app.configured,
-                    data.app.width,
-                    data.app.height
-                );
-            }
-            let pruned = data.canvas.prune();
-            if pruned {
-                data.dirty = true;
-            }
-            let mut cmd_count = 0;
-            while let Ok(cmd) = data.receiver.try_recv() {
-                log::info!(
-                    "Received command with {} operations on layer {:?}",
-                    cmd.operations.len(),
-                    cmd.layer
-                );
-                data.canvas.update(cmd);
-                data.dirty = true;
-                cmd_count += 1;
-            }
-            if cmd_count > 0 {
-                log::info!("Processed {} commands", cmd_count);
+        .insert_source(receiver, |event, _, data| {
+            if let ChannelEvent::Msg(request) = event {
+                data.handle_request(request);
+                data.redraw();
             }
-            if data.app.needs_render || cmd_count > 0 || pruned || data.dirty {
-                data.canvas.render(&mut data.app.renderer);
-                data.app.render_if_configured();
-                data.app.needs_render = false;
-                data.dirty = false;
-            }
-            data.frame_count += 1;
-            calloop::timer::TimeoutAction::ToDuration(std::time::Duration::from_millis(16))
         })
-        .expect("Failed to insert timer");
+        .expect("Failed to insert command channel");
+    event_loop
+        .handle()
+        .insert_source(signals, |_, _, data| data.reload())
+        .expect("Failed to insert signal source");
+    log::info!("Starting main event loop");
     event_loop
         .run(None, &mut data, |_| {})
         .expect("Failed to run event loop");
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn tls_flags_override_config_per_file() {
+        let path = std::env::temp_dir().join(format!("overlay-{}-tls.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "tcp = \"0.0.0.0:7436\"\n[tls]\ncert = \"cert.pem\"\nclient_ca = \"ca.pem\"\n",
+        )
+        .unwrap();
+        let config = Config::load(Some(&path));
+        let _ = std::fs::remove_file(&path);
+        let config = config.unwrap();
+        // The TCP address comes from the config, so --tcp isn't needed
+        let args = Args::try_parse_from([
+            "overlay",
+            "--tls-cert",
+            "/etc/overlay/cert.pem",
+            "--tls-key",
+            "/etc/overlay/key.pem",
+        ])
+        .unwrap();
+        let tls = tls_files(&args, &config);
+        assert_eq!(tls.cert, Some(PathBuf::from("/etc/overlay/cert.pem")));
+        assert_eq!(tls.key, Some(PathBuf::from("/etc/overlay/key.pem")));
+        assert_eq!(tls.client_ca, Some(std::env::temp_dir().join("ca.pem")));
+    }
+}
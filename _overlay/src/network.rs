@@ -1,36 +1,384 @@
-use crate::draw::DrawOperation;
 use anyhow::Result;
-use crossbeam::channel::Sender;
-use serde::Deserialize;
-use tokio::io::AsyncReadExt;
+use calloop::channel::Sender;
+use overlay_protocol::auth::{Verifier, authenticate};
+use overlay_protocol::command::{Command, OutputTarget, Subscription, parse_command};
+use overlay_protocol::frame::{Encoding, encode_frame};
+use overlay_protocol::response::{ErrorKind, Event, Response};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_rustls::TlsAcceptor;
+/// Pins `Focused` to the output that currently has focus, so the layer stays put when focus
+/// moves. Falls back to all outputs if the focus isn't known.
+pub fn resolve_output(target: OutputTarget, focused: &FocusedOutput) -> OutputTarget {
+    match target {
+        OutputTarget::Focused => focused.get().map_or(OutputTarget::All, OutputTarget::Named),
+        target => target,
+    }
+}
 #[derive(Deserialize)]
-pub struct Command {
-    pub layer: Option<i32>,
-    pub timeout_ms: Option<u64>,
-    pub operations: Vec<DrawOperation>,
+struct HyprMonitor {
+    name: String,
+    focused: bool,
+}
+/// The output that last had focus. Wayland has no notion of a focused output, so this follows
+/// Hyprland's monitor focus on the network runtime and stays unknown elsewhere.
+#[derive(Clone, Default)]
+pub struct FocusedOutput(Arc<Mutex<Option<String>>>);
+impl FocusedOutput {
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+    fn set(&self, name: &str) {
+        *self.0.lock().unwrap() = Some(name.to_string());
+    }
+    /// Asks hyprctl for the focused monitor, then follows `focusedmon` events on Hyprland's
+    /// event socket. Returns at once outside Hyprland.
+    pub async fn track(self) -> Result<()> {
+        let Ok(signature) = std::env::var("HYPRLAND_INSTANCE_SIGNATURE") else {
+            return Ok(());
+        };
+        let runtime_dir = std::env::var("XDG_RUNTIME_DIR")?;
+        let path = std::path::Path::new(&runtime_dir)
+            .join("hypr")
+            .join(signature)
+            .join(".socket2.sock");
+        // Connect first so a focus change during the query isn't missed
+        let events = tokio::net::UnixStream::connect(&path).await?;
+        let query = tokio::process::Command::new("hyprctl")
+            .args(["monitors", "-j"])
+            .output();
+        match tokio::time::timeout(HYPRCTL_TIMEOUT, query).await {
+            Ok(Ok(output)) if output.status.success() => {
+                let monitors: Vec<HyprMonitor> = serde_json::from_slice(&output.stdout)?;
+                if let Some(monitor) = monitors.iter().find(|m| m.focused) {
+                    self.set(&monitor.name);
+                }
+            }
+            _ => log::warn!("hyprctl didn't report the focused monitor"),
+        }
+        let mut lines = BufReader::new(events).lines();
+        while let Some(line) = lines.next_line().await? {
+            // focusedmon>>MONITOR,WORKSPACE
+            if let Some((name, _)) = line
+                .strip_prefix("focusedmon>>")
+                .and_then(|data| data.split_once(','))
+            {
+                self.set(name);
+            }
+        }
+        Ok(())
+    }
+}
+const HYPRCTL_TIMEOUT: Duration = Duration::from_secs(1);
+#[derive(Debug)]
+pub enum CommandError {
+    UnknownLayer(i32),
+    Snapshot(String),
+    InvalidColor(String),
+}
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::UnknownLayer(layer) => write!(f, "unknown layer {}", layer),
+            CommandError::Snapshot(message) => write!(f, "snapshot failed: {}", message),
+            CommandError::InvalidColor(message) => write!(f, "{}", message),
+        }
+    }
+}
+impl std::error::Error for CommandError {}
+impl From<CommandError> for Response {
+    fn from(e: CommandError) -> Self {
+        let kind = match e {
+            CommandError::UnknownLayer(_) => ErrorKind::UnknownLayer,
+            CommandError::Snapshot(_) => ErrorKind::Snapshot,
+            CommandError::InvalidColor(_) => ErrorKind::InvalidColor,
+        };
+        Self::error(kind, e.to_string())
+    }
+}
+/// A command on its way to the main thread, with a channel for the result when the
+/// client is waiting for one.
+pub struct Request {
+    pub command: Command,
+    pub reply: Option<oneshot::Sender<Response>>,
+}
+async fn submit(sender: &Sender<Request>, command: Command) -> Response {
+    let (reply, response) = oneshot::channel();
+    let request = Request {
+        command,
+        reply: Some(reply),
+    };
+    if sender.send(request).is_err() {
+        return Response::error(ErrorKind::Internal, "overlay is shutting down");
+    }
+    response
+        .await
+        .unwrap_or_else(|_| Response::error(ErrorKind::Internal, "command was dropped"))
+}
+async fn next_event(
+    events: &mut Option<broadcast::Receiver<Event>>,
+) -> Result<Event, broadcast::error::RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+/// How commands are delimited on a stream connection, chosen by its first byte.
+#[derive(Clone, Copy)]
+enum Framing {
+    /// Newline-delimited JSON. A command may also span several lines, as pretty-printed
+    /// JSON does.
+    Lines,
+    /// Length-prefixed frames, after a leading byte that picks their encoding.
+    Frames(Encoding),
+}
+async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    framing: Framing,
+    value: &impl Serialize,
+) -> Result<()> {
+    let message = match framing {
+        Framing::Lines => [serde_json::to_vec(value)?, b"\n".to_vec()].concat(),
+        Framing::Frames(encoding) => {
+            encode_frame(&encoding.encode(value).map_err(anyhow::Error::msg)?)
+        }
+    };
+    writer.write_all(&message).await?;
+    Ok(())
+}
+fn frame_too_large(max_frame_size: usize) -> Response {
+    Response::error(
+        ErrorKind::FrameTooLarge,
+        format!("command exceeds the maximum of {} bytes", max_frame_size),
+    )
+}
+/// Reads commands off a connection and passes them on, or the response to send back when
+/// one can't be parsed. Stops after a command that is too large, as the rest of the
+/// stream can't be trusted to line up.
+async fn read_commands<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+    framing: Framing,
+    max_frame_size: usize,
+    commands: mpsc::Sender<Result<Command, Response>>,
+) -> Result<()> {
+    match framing {
+        Framing::Lines => {
+            let mut pending = String::new();
+            let mut pending_start = 0;
+            let mut line_number = 0;
+            loop {
+                let mut line = String::new();
+                let limit = (max_frame_size - pending.len()) as u64 + 1;
+                let eof = (&mut reader).take(limit).read_line(&mut line).await? == 0;
+                if !eof {
+                    let start = line_number + 1;
+                    if line.ends_with('\n') {
+                        line_number += 1;
+                    }
+                    if pending.is_empty() {
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        pending_start = start;
+                    }
+                    pending.push_str(&line);
+                    if pending.len() > max_frame_size {
+                        let _ = commands.send(Err(frame_too_large(max_frame_size))).await;
+                        return Ok(());
+                    }
+                }
+                if pending.is_empty() {
+                    return Ok(());
+                }
+                log::debug!("JSON: {}", pending);
+                let incoming = match parse_command(&pending) {
+                    Err(e) if e.is_eof() && !eof => continue,
+                    Err(e) => {
+                        log::error!("Failed to parse JSON: {}", e);
+                        Err(Response::parse_error(&e, pending_start))
+                    }
+                    Ok(command) => Ok(command),
+                };
+                pending.clear();
+                if commands.send(incoming).await.is_err() || eof {
+                    return Ok(());
+                }
+            }
+        }
+        Framing::Frames(encoding) => loop {
+            let mut length = [0; 4];
+            match reader.read_exact(&mut length).await {
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                result => result?,
+            };
+            let length = u32::from_be_bytes(length) as usize;
+            if length > max_frame_size {
+                let _ = commands.send(Err(frame_too_large(max_frame_size))).await;
+                return Ok(());
+            }
+            let mut frame = vec![0; length];
+            reader.read_exact(&mut frame).await?;
+            let incoming = match (encoding, std::str::from_utf8(&frame)) {
+                (Encoding::Json, Ok(json)) => {
+                    log::debug!("JSON: {}", json);
+                    parse_command(json).map_err(|e| {
+                        log::error!("Failed to parse JSON: {}", e);
+                        Response::parse_error(&e, 1)
+                    })
+                }
+                (Encoding::Json, Err(_)) => {
+                    Err(Response::error(ErrorKind::Parse, "frame is not UTF-8"))
+                }
+                (encoding, _) => encoding.decode(&frame).map_err(|e| {
+                    log::error!("Failed to decode {:?} frame: {}", encoding, e);
+                    Response::error(ErrorKind::Parse, e)
+                }),
+            };
+            if commands.send(incoming).await.is_err() {
+                return Ok(());
+            }
+        },
+    }
+}
+/// Serves commands on one stream connection, answering each with a response. Newline-
+/// delimited JSON is expected unless the connection opens with one of the `FRAMED_*` bytes.
+async fn handle_stream<S: AsyncRead + AsyncWrite + Send + 'static>(
+    stream: S,
+    sender: Sender<Request>,
+    events: broadcast::Sender<Event>,
+    max_frame_size: usize,
+) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let framing = match reader.fill_buf().await?.first() {
+        None => return Ok(()),
+        Some(&byte) => match Encoding::from_magic(byte) {
+            Some(encoding) => {
+                reader.consume(1);
+                Framing::Frames(encoding)
+            }
+            None => Framing::Lines,
+        },
+    };
+    // Reading a frame isn't cancel safe, so read on a separate task and select on the channel
+    let (commands_sender, mut commands) = mpsc::channel(16);
+    let reading = tokio::spawn(read_commands(
+        reader,
+        framing,
+        max_frame_size,
+        commands_sender,
+    ));
+    let mut subscription = None;
+    loop {
+        let incoming = tokio::select! {
+            incoming = commands.recv() => incoming,
+            event = next_event(&mut subscription) => {
+                match event {
+                    Ok(event) => write_message(&mut writer, framing, &event).await?,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        log::warn!("Subscriber fell behind, dropped {} events", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => subscription = None,
+                }
+                continue;
+            }
+        };
+        let Some(incoming) = incoming else {
+            break;
+        };
+        let response = match incoming {
+            Err(response) => response,
+            Ok(Command::Subscription(Subscription::Subscribe)) => {
+                subscription = Some(events.subscribe());
+                Response::ok()
+            }
+            Ok(Command::Subscription(Subscription::Unsubscribe)) => {
+                subscription = None;
+                Response::ok()
+            }
+            Ok(cmd) => {
+                log::info!("Parsed command successfully, sending to main thread");
+                submit(&sender, cmd).await
+            }
+        };
+        write_message(&mut writer, framing, &response).await?;
+    }
+    reading.await?
 }
+pub struct UdpListener {
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Shared secret that commands must be signed with, if any.
+    pub secret: Option<Vec<u8>>,
+}
+/// Accepts the same stream protocol as the Unix socket, for controlling the overlay from
+/// another machine.
+pub struct TcpListener {
+    pub addr: SocketAddr,
+    pub tls: Option<TlsAcceptor>,
+}
+/// Clients that haven't finished the TLS handshake by then are disconnected.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 pub async fn start_listeners(
-    port: Option<u16>,
+    udp: Option<UdpListener>,
+    tcp: Option<TcpListener>,
     unix_path: Option<&str>,
-    sender: Sender<Command>,
+    max_frame_size: usize,
+    sender: Sender<Request>,
+    events: broadcast::Sender<Event>,
 ) -> Result<()> {
     let mut handles = vec![];
-    if let Some(port) = port {
+    if let Some(udp) = udp {
         let sender = sender.clone();
-        log::info!("Starting UDP listener on port {}", port);
+        log::info!("Starting UDP listener on {}:{}", udp.bind, udp.port);
+        if udp.secret.is_some() {
+            log::info!("UDP commands must carry a valid HMAC");
+        } else if !udp.bind.is_loopback() {
+            log::warn!(
+                "UDP listener on {} accepts unauthenticated commands",
+                udp.bind
+            );
+        }
         let handle = tokio::spawn(async move {
-            let socket = tokio::net::UdpSocket::bind(("0.0.0.0", port)).await?;
+            let socket = tokio::net::UdpSocket::bind((udp.bind, udp.port)).await?;
             log::info!("UDP socket bound successfully");
             let mut buf = [0; 65536];
+            let mut verifier = udp.secret.map(Verifier::new);
             loop {
                 let (len, addr) = socket.recv_from(&mut buf).await?;
                 log::info!("Received {} bytes from {}", len, addr);
-                let json = std::str::from_utf8(&buf[..len])?;
-                log::debug!("JSON: {}", json);
-                match serde_json::from_str::<Command>(json) {
-                    Ok(cmd) => {
+                let Ok(packet) = std::str::from_utf8(&buf[..len]) else {
+                    log::warn!("Rejected UDP packet from {}: not UTF-8", addr);
+                    continue;
+                };
+                log::debug!("JSON: {}", packet);
+                let json = match authenticate(packet, verifier.as_mut()) {
+                    Ok(json) => json,
+                    Err(reason) => {
+                        log::warn!("Rejected UDP packet from {}: {}", addr, reason);
+                        continue;
+                    }
+                };
+                match parse_command(json) {
+                    Ok(Command::Subscription(_)) => {
+                        log::warn!("Ignoring subscription over UDP, use the Unix socket");
+                    }
+                    Ok(Command::Query(_)) => {
+                        log::warn!("Ignoring query over UDP, which has no replies");
+                    }
+                    Ok(command) => {
                         log::info!("Parsed command successfully, sending to main thread");
-                        if let Err(e) = sender.send(cmd) {
+                        let request = Request {
+                            command,
+                            reply: None,
+                        };
+                        if let Err(e) = sender.send(request) {
                             log::error!("Failed to send command: {}", e);
                         }
                     }
@@ -44,6 +392,51 @@ pub async fn start_listeners(
         });
         handles.push(handle);
     }
+    if let Some(tcp) = tcp {
+        let sender = sender.clone();
+        let events = events.clone();
+        log::info!("Starting TCP listener on {}", tcp.addr);
+        if tcp.tls.is_none() && !tcp.addr.ip().is_loopback() {
+            log::warn!(
+                "TCP listener on {} accepts unencrypted, unauthenticated commands",
+                tcp.addr
+            );
+        }
+        let handle = tokio::spawn(async move {
+            let listener = tokio::net::TcpListener::bind(tcp.addr).await?;
+            log::info!("TCP socket bound successfully");
+            loop {
+                let (stream, addr) = listener.accept().await?;
+                log::info!("TCP connection accepted from {}", addr);
+                let sender = sender.clone();
+                let events = events.clone();
+                let tls = tcp.tls.clone();
+                tokio::spawn(async move {
+                    let result = match tls {
+                        Some(tls) => {
+                            let handshake = tls.accept(stream);
+                            match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, handshake).await {
+                                Ok(Ok(stream)) => {
+                                    handle_stream(stream, sender, events, max_frame_size).await
+                                }
+                                Ok(Err(e)) => {
+                                    Err(anyhow::Error::new(e).context("TLS handshake failed"))
+                                }
+                                Err(_) => Err(anyhow::anyhow!("TLS handshake timed out")),
+                            }
+                        }
+                        None => handle_stream(stream, sender, events, max_frame_size).await,
+                    };
+                    if let Err(e) = result {
+                        log::warn!("TCP connection from {} closed: {:#}", addr, e);
+                    }
+                });
+            }
+            #[allow(unreachable_code)]
+            Ok::<(), anyhow::Error>(())
+        });
+        handles.push(handle);
+    }
     if let Some(path) = unix_path {
         let sender = sender.clone();
         if std::path::Path::new(path).exists() {
@@ -56,27 +449,14 @@ pub async fn start_listeners(
             let listener = tokio::net::UnixListener::bind(&path)?;
             log::info!("Unix socket bound successfully at {}", path);
             loop {
-                let (mut stream, _) = listener.accept().await?;
+                let (stream, _) = listener.accept().await?;
                 log::info!("Unix socket connection accepted");
                 let sender = sender.clone();
+                let events = events.clone();
                 tokio::spawn(async move {
-                    let mut buf = Vec::new();
-                    stream.read_to_end(&mut buf).await?;
-                    log::info!("Read {} bytes from Unix socket", buf.len());
-                    let json = std::str::from_utf8(&buf)?;
-                    log::debug!("JSON: {}", json);
-                    match serde_json::from_str::<Command>(json) {
-                        Ok(cmd) => {
-                            log::info!("Parsed command successfully, sending to main thread");
-                            if let Err(e) = sender.send(cmd) {
-                                log::error!("Failed to send command: {}", e);
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("Failed to parse JSON: {}", e);
-                        }
+                    if let Err(e) = handle_stream(stream, sender, events, max_frame_size).await {
+                        log::warn!("Unix socket connection closed: {}", e);
                     }
-                    Ok::<(), anyhow::Error>(())
                 });
             }
             #[allow(unreachable_code)]
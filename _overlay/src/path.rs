@@ -0,0 +1,29 @@
+use anyhow::{Result, anyhow};
+use svgtypes::{SimplePathSegment, SimplifyingPathParser};
+use tiny_skia::{Path, PathBuilder};
+/// Parses SVG path data into a tiny-skia path. Relative commands, shorthand curves and
+/// elliptical arcs are normalized by the parser into absolute lines and curves.
+pub fn parse_svg_path(d: &str) -> Result<Path> {
+    let mut builder = PathBuilder::new();
+    for segment in SimplifyingPathParser::from(d) {
+        match segment.map_err(|e| anyhow!("invalid path data: {}", e))? {
+            SimplePathSegment::MoveTo { x, y } => builder.move_to(x as f32, y as f32),
+            SimplePathSegment::LineTo { x, y } => builder.line_to(x as f32, y as f32),
+            SimplePathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => builder.cubic_to(
+                x1 as f32, y1 as f32, x2 as f32, y2 as f32, x as f32, y as f32,
+            ),
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                builder.quad_to(x1 as f32, y1 as f32, x as f32, y as f32)
+            }
+            SimplePathSegment::ClosePath => builder.close(),
+        }
+    }
+    builder.finish().ok_or_else(|| anyhow!("path is empty"))
+}
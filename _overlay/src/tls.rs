@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use overlay_protocol::tls::load_certs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+/// Builds the TLS side of the TCP listener from PEM files. With `client_ca`, clients must
+/// present a certificate issued by it.
+pub fn load_acceptor(cert: &Path, key: &Path, client_ca: Option<&Path>) -> Result<TlsAcceptor> {
+    let provider = Arc::new(ring::default_provider());
+    let certs = load_certs(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("failed to read private key from {}", key.display()))?;
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(path)? {
+                roots.add(cert)?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(certs, key)
+        .context("certificate doesn't match the private key")?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{Request, TcpListener, start_listeners};
+    use overlay_protocol::OverlayClient;
+    use overlay_protocol::command::LayerAction;
+    use overlay_protocol::frame::DEFAULT_MAX_FRAME_SIZE;
+    use overlay_protocol::response::Response;
+    use overlay_protocol::tls::TlsOptions;
+    use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+    use tokio::sync::broadcast;
+    /// PEM files for a test, removed afterwards.
+    struct Pki {
+        dir: PathBuf,
+    }
+    impl Pki {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("overlay-{}-{}", std::process::id(), name));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self { dir }
+        }
+        fn write(&self, name: &str, pem: &str) -> PathBuf {
+            let path = self.dir.join(name);
+            std::fs::write(&path, pem).unwrap();
+            path
+        }
+        fn ca(&self, name: &str) -> (CertifiedIssuer<'static, KeyPair>, PathBuf) {
+            let mut params = CertificateParams::new(Vec::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+            let path = self.write(&format!("{}.pem", name), &ca.pem());
+            (ca, path)
+        }
+        /// Issues a certificate for `san` and returns the certificate and key files.
+        fn issue(
+            &self,
+            ca: &CertifiedIssuer<'static, KeyPair>,
+            name: &str,
+            san: &str,
+        ) -> (PathBuf, PathBuf) {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![san.to_string()])
+                .unwrap()
+                .signed_by(&key, ca)
+                .unwrap();
+            (
+                self.write(&format!("{}.pem", name), &cert.pem()),
+                self.write(&format!("{}.key", name), &key.serialize_pem()),
+            )
+        }
+    }
+    impl Drop for Pki {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+    /// Starts a TCP listener on loopback that answers every command with success.
+    fn serve(tls: TlsAcceptor) -> (SocketAddr, tokio::runtime::Runtime) {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let (sender, receiver) = calloop::channel::channel::<Request>();
+        std::thread::spawn(move || {
+            while let Ok(request) = receiver.recv() {
+                if let Some(reply) = request.reply {
+                    let _ = reply.send(Response::ok());
+                }
+            }
+        });
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (events, _) = broadcast::channel(1);
+        let tcp = TcpListener {
+            addr,
+            tls: Some(tls),
+        };
+        rt.spawn(start_listeners(
+            None,
+            Some(tcp),
+            None,
+            DEFAULT_MAX_FRAME_SIZE,
+            sender,
+            events,
+        ));
+        while std::net::TcpStream::connect(addr).is_err() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        (addr, rt)
+    }
+    fn clear(addr: SocketAddr, tls: &TlsOptions) -> std::io::Result<Option<Response>> {
+        OverlayClient::tcp_tls(addr, tls)?.send(LayerAction::ClearAll)
+    }
+    #[test]
+    fn client_certificates() {
+        let pki = Pki::new("tls");
+        let (ca, ca_path) = pki.ca("ca");
+        let (rogue_ca, rogue_ca_path) = pki.ca("rogue-ca");
+        let (server_cert, server_key) = pki.issue(&ca, "server", "127.0.0.1");
+        let (client_cert, client_key) = pki.issue(&ca, "client", "client");
+        let (rogue_cert, rogue_key) = pki.issue(&rogue_ca, "rogue", "client");
+        let acceptor = load_acceptor(&server_cert, &server_key, Some(&ca_path)).unwrap();
+        let (addr, _rt) = serve(acceptor);
+        let trusted = TlsOptions::new(&ca_path).identity(&client_cert, &client_key);
+        let response = clear(addr, &trusted).unwrap().unwrap();
+        assert!(response.ok);
+        let anonymous = TlsOptions::new(&ca_path);
+        assert!(clear(addr, &anonymous).is_err());
+        let rogue = TlsOptions::new(&ca_path).identity(&rogue_cert, &rogue_key);
+        assert!(clear(addr, &rogue).is_err());
+        // The client must not trust a server its CA didn't issue
+        let wrong_ca = TlsOptions::new(&rogue_ca_path).identity(&client_cert, &client_key);
+        assert!(clear(addr, &wrong_ca).is_err());
+    }
+}
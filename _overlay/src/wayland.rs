@@ -1,38 +1,107 @@
-use crate::draw::Renderer;
+use crate::config::{Config, Margins, SurfaceLayer};
+use crate::damage::merge;
+use crate::draw::{CanvasState, Renderer};
+use overlay_protocol::response::{Event, SurfaceInfo};
 use smithay_client_toolkit::{
     compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm,
+    delegate_compositor, delegate_layer, delegate_output, delegate_pointer, delegate_registry,
+    delegate_seat, delegate_shm,
     output::{OutputHandler, OutputState},
     registry::{ProvidesRegistryState, RegistryState},
+    seat::{
+        Capability, SeatHandler, SeatState,
+        pointer::{PointerEvent, PointerEventKind, PointerHandler},
+    },
     shell::WaylandSurface,
     shell::wlr_layer::{
         Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
         LayerSurfaceConfigure,
     },
-    shm::{Shm, ShmHandler, slot::SlotPool},
+    shm::{Shm, ShmHandler, raw::RawPool},
 };
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tiny_skia::{IntRect, Pixmap};
 use wayland_client::{
-    Connection, Dispatch, EventQueue, QueueHandle,
+    Connection, Dispatch, EventQueue, QueueHandle, delegate_noop,
     globals::registry_queue_init,
-    protocol::{wl_output, wl_region::WlRegion, wl_shm, wl_surface},
+    protocol::{
+        wl_buffer, wl_output, wl_pointer, wl_region::WlRegion, wl_seat, wl_shm, wl_surface,
+    },
+};
+use wayland_protocols::wp::fractional_scale::v1::client::{
+    wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+    wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+};
+use wayland_protocols::wp::viewporter::client::{
+    wp_viewport::WpViewport, wp_viewporter::WpViewporter,
 };
+/// Buffers kept per surface. Two alternate normally; a third is allocated only when the
+/// compositor is still holding both.
+const MAX_BUFFERS: usize = 3;
+/// A shared-memory buffer and the regions where it lags behind the renderer's pixmap.
+pub struct SwapBuffer {
+    buffer: wl_buffer::WlBuffer,
+    /// Byte offset of the buffer in the surface's pool.
+    offset: usize,
+    /// Set while the compositor may read the buffer, cleared by `wl_buffer.release`.
+    busy: Arc<AtomicBool>,
+    stale: Vec<IntRect>,
+}
+impl Drop for SwapBuffer {
+    fn drop(&mut self) {
+        self.buffer.destroy();
+    }
+}
+/// A layer surface covering a single output, with its own render target.
+pub struct OverlaySurface {
+    pub output: wl_output::WlOutput,
+    pub output_name: Option<String>,
+    pub layer_surface: LayerSurface,
+    pub renderer: Renderer,
+    pub width: u32,
+    pub height: u32,
+    pub pool: Option<RawPool>,
+    pub buffers: Vec<SwapBuffer>,
+    /// Regions drawn into the pixmap but not yet committed to the surface.
+    pub pending_damage: Vec<IntRect>,
+    /// Clickable rectangles currently set as the input region, in logical pixels.
+    pub input_rects: Vec<IntRect>,
+    /// Layer and id of the clickable region under the pointer.
+    pub hovered: Option<(i32, String)>,
+    pub configured: bool,
+    /// Set when the canvas changed since this surface was last drawn.
+    pub needs_render: bool,
+    /// A frame callback was requested and the compositor hasn't asked for the next frame yet.
+    pub frame_pending: bool,
+    /// Integer buffer scale from `wl_surface`, used when fractional scaling is unavailable.
+    pub scale_factor: i32,
+    /// Preferred scale from `wp_fractional_scale_v1`, presented through the viewport.
+    pub fractional_scale: Option<f64>,
+    pub viewport: Option<WpViewport>,
+    pub fractional: Option<WpFractionalScaleV1>,
+}
 pub struct WaylandApp {
     pub registry_state: RegistryState,
     pub output_state: OutputState,
     pub compositor_state: CompositorState,
     pub layer_shell: LayerShell,
     pub shm: Shm,
-    pub layer_surface: Option<LayerSurface>,
-    pub surface: Option<wl_surface::WlSurface>,
-    pub renderer: Renderer,
-    pub width: u32,
-    pub height: u32,
-    pub slot_pool: Option<SlotPool>,
-    pub configured: bool,
-    pub needs_render: bool,
+    pub fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    pub viewporter: Option<WpViewporter>,
+    pub seat_state: SeatState,
+    pub pointer: Option<wl_pointer::WlPointer>,
+    /// Pointer input received since it was last turned into events.
+    pub pointer_events: Vec<PointerEvent>,
+    /// Events for subscribers raised by the Wayland handlers.
+    events: Vec<Event>,
+    pub surfaces: Vec<OverlaySurface>,
+    layer: Layer,
+    margin: Margins,
+    qh: QueueHandle<Self>,
 }
 impl WaylandApp {
-    pub fn new(width: u32, height: u32) -> (Self, EventQueue<Self>, Connection) {
+    pub fn new() -> (Self, EventQueue<Self>, Connection) {
         let conn = Connection::connect_to_env().unwrap();
         let (globals, event_queue) = registry_queue_init(&conn).unwrap();
         let qh = event_queue.handle();
@@ -41,7 +110,18 @@ impl WaylandApp {
         let compositor_state = CompositorState::bind(&globals, &qh).unwrap();
         let layer_shell = LayerShell::bind(&globals, &qh).unwrap();
         let shm = Shm::bind(&globals, &qh).unwrap();
-        let renderer = Renderer::new(width, height);
+        let seat_state = SeatState::new(&globals, &qh);
+        // Fractional scaling needs both protocols; fall back to integer buffer scale otherwise
+        let (fractional_scale_manager, viewporter) = match (
+            globals.bind::<WpFractionalScaleManagerV1, _, _>(&qh, 1..=1, ()),
+            globals.bind::<WpViewporter, _, _>(&qh, 1..=1, ()),
+        ) {
+            (Ok(manager), Ok(viewporter)) => (Some(manager), Some(viewporter)),
+            _ => {
+                log::info!("Fractional scaling unavailable, using integer buffer scale");
+                (None, None)
+            }
+        };
         (
             Self {
                 registry_state,
@@ -49,79 +129,354 @@ impl WaylandApp {
                 compositor_state,
                 layer_shell,
                 shm,
-                layer_surface: None,
-                surface: None,
-                renderer,
-                width,
-                height,
-                slot_pool: None,
-                configured: false,
-                needs_render: false,
+                fractional_scale_manager,
+                viewporter,
+                seat_state,
+                pointer: None,
+                pointer_events: Vec::new(),
+                events: Vec::new(),
+                surfaces: Vec::new(),
+                layer: Layer::Overlay,
+                margin: Margins::default(),
+                qh: qh.clone(),
             },
             event_queue,
             conn,
         )
     }
-    pub fn create_layer_surface(&mut self, qh: &QueueHandle<Self>) {
+    pub fn create_layer_surface(&mut self, qh: &QueueHandle<Self>, output: wl_output::WlOutput) {
+        let output_name = self.output_state.info(&output).and_then(|info| info.name);
         let surface = self.compositor_state.create_surface(qh);
         let compositor = self.compositor_state.wl_compositor();
         let region = compositor.create_region(qh, ());
         surface.set_input_region(Some(&region));
-        self.surface = Some(surface.clone());
+        let (viewport, fractional) = match (&self.viewporter, &self.fractional_scale_manager) {
+            (Some(viewporter), Some(manager)) => (
+                Some(viewporter.get_viewport(&surface, qh, ())),
+                Some(manager.get_fractional_scale(&surface, qh, surface.clone())),
+            ),
+            _ => (None, None),
+        };
         let layer_surface = self.layer_shell.create_layer_surface(
             qh,
             surface,
-            Layer::Overlay,
+            self.layer,
             Some("wayland-overlay"),
-            None,
+            Some(&output),
         );
         layer_surface.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
         layer_surface.set_exclusive_zone(-1);
+        let margin = self.margin;
+        layer_surface.set_margin(margin.top, margin.right, margin.bottom, margin.left);
         layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
         layer_surface.commit();
-        self.layer_surface = Some(layer_surface);
-        self.slot_pool =
-            Some(SlotPool::new(self.width as usize * self.height as usize * 4, &self.shm).unwrap());
+        log::info!("Layer surface created for output {:?}", output_name);
+        self.surfaces.push(OverlaySurface {
+            output,
+            output_name,
+            layer_surface,
+            renderer: Renderer::new(1, 1),
+            width: 0,
+            height: 0,
+            pool: None,
+            buffers: Vec::new(),
+            pending_damage: Vec::new(),
+            input_rects: Vec::new(),
+            hovered: None,
+            configured: false,
+            needs_render: false,
+            frame_pending: false,
+            scale_factor: 1,
+            fractional_scale: None,
+            viewport,
+            fractional,
+        });
     }
-    pub fn render_if_configured(&mut self) {
-        if !self.configured {
+    /// Moves the surfaces to the configured layer and margins.
+    pub fn apply_config(&mut self, config: &Config) {
+        let layer = match config.layer {
+            SurfaceLayer::Background => Layer::Background,
+            SurfaceLayer::Bottom => Layer::Bottom,
+            SurfaceLayer::Top => Layer::Top,
+            SurfaceLayer::Overlay => Layer::Overlay,
+        };
+        if layer == self.layer && config.margin == self.margin {
             return;
         }
-        if let Some(surface) = &self.surface {
-            let width = self.renderer.pixmap.width() as i32;
-            let height = self.renderer.pixmap.height() as i32;
-            let stride = width * 4;
-            if let Some(pool) = &mut self.slot_pool {
-                let size = (stride * height) as usize;
-                if pool.len() < size {
-                    if let Err(e) = pool.resize(size) {
-                        log::error!("Failed to resize pool: {}", e);
-                        return;
-                    }
+        self.layer = layer;
+        self.margin = config.margin;
+        let margin = self.margin;
+        for surface in &self.surfaces {
+            let layer_surface = &surface.layer_surface;
+            layer_surface.set_layer(layer);
+            layer_surface.set_margin(margin.top, margin.right, margin.bottom, margin.left);
+            // The compositor answers with a configure carrying the new size
+            layer_surface.commit();
+        }
+    }
+    /// Makes the clickable regions of each surface accept pointer input, leaving the rest
+    /// click-through.
+    pub fn update_input_regions(&mut self, canvas: &CanvasState) {
+        for surface in &mut self.surfaces {
+            if !surface.configured {
+                continue;
+            }
+            let rects: Vec<IntRect> = canvas
+                .clickables(&surface.renderer, surface.output_name.as_deref())
+                .iter()
+                .filter_map(|c| c.rect.round_out())
+                .collect();
+            if rects == surface.input_rects {
+                continue;
+            }
+            let region = self
+                .compositor_state
+                .wl_compositor()
+                .create_region(&self.qh, ());
+            for rect in &rects {
+                region.add(
+                    rect.x(),
+                    rect.y(),
+                    rect.width() as i32,
+                    rect.height() as i32,
+                );
+            }
+            let wl_surface = surface.layer_surface.wl_surface();
+            wl_surface.set_input_region(Some(&region));
+            wl_surface.commit();
+            region.destroy();
+            surface.input_rects = rects;
+        }
+    }
+    /// Returns the events raised since the last call, including pointer input hit-tested
+    /// against the clickable regions.
+    pub fn take_events(&mut self, canvas: &CanvasState) -> Vec<Event> {
+        let mut events = std::mem::take(&mut self.events);
+        for input in std::mem::take(&mut self.pointer_events) {
+            let Some(surface) = self
+                .surfaces
+                .iter_mut()
+                .find(|s| s.layer_surface.wl_surface() == &input.surface)
+            else {
+                continue;
+            };
+            let output = surface.output_name.clone();
+            let (x, y) = input.position;
+            let hit = canvas
+                .clickables(&surface.renderer, output.as_deref())
+                .into_iter()
+                .find(|c| {
+                    let (x, y) = (x as f32, y as f32);
+                    x >= c.rect.left()
+                        && x < c.rect.right()
+                        && y >= c.rect.top()
+                        && y < c.rect.bottom()
+                })
+                .map(|c| (c.layer, c.id.to_string()));
+            let hovered = match input.kind {
+                PointerEventKind::Leave { .. } => None,
+                _ => hit.clone(),
+            };
+            if hovered != surface.hovered {
+                if let Some((layer, id)) = surface.hovered.take() {
+                    let output = output.clone();
+                    events.push(Event::Leave { id, layer, output });
                 }
-                match pool.create_buffer(width, height, stride, wl_shm::Format::Argb8888) {
-                    Ok((buffer, canvas)) => {
-                        let data = self.renderer.pixmap.data();
-                        for i in 0..(data.len() / 4) {
-                            let r = data[i * 4];
-                            let g = data[i * 4 + 1];
-                            let b = data[i * 4 + 2];
-                            let a = data[i * 4 + 3];
-                            canvas[i * 4] = b; // B
-                            canvas[i * 4 + 1] = g; // G
-                            canvas[i * 4 + 2] = r; // R
-                            canvas[i * 4 + 3] = a; // A
-                        }
-                        surface.attach(Some(buffer.wl_buffer()), 0, 0);
-                        surface.damage_buffer(0, 0, width, height);
-                        surface.commit();
-                    }
-                    Err(e) => {
-                        log::error!("Failed to create buffer: {}", e);
-                    }
+                if let Some((layer, id)) = hovered.clone() {
+                    let output = output.clone();
+                    events.push(Event::Enter { id, layer, output });
                 }
+                surface.hovered = hovered;
+            }
+            if let PointerEventKind::Press { button, .. } = input.kind
+                && let Some((layer, id)) = hit
+            {
+                events.push(Event::Click {
+                    id,
+                    layer,
+                    button,
+                    x,
+                    y,
+                    output,
+                });
             }
         }
+        events
+    }
+    /// Marks every surface as needing a redraw.
+    pub fn invalidate(&mut self) {
+        for surface in &mut self.surfaces {
+            surface.needs_render = true;
+        }
+    }
+    /// Whether a frame callback will arrive to drive the next redraw.
+    pub fn frame_pending(&self) -> bool {
+        self.surfaces.iter().any(|s| s.frame_pending)
+    }
+    /// Renders the canvas onto surfaces that need it and are ready for a new frame.
+    /// Returns the number of surfaces drawn.
+    pub fn render(&mut self, canvas: &mut CanvasState) -> usize {
+        let mut rendered = 0;
+        for surface in &mut self.surfaces {
+            if !surface.configured || surface.frame_pending {
+                continue;
+            }
+            if surface.needs_render {
+                let output = surface.output_name.as_deref();
+                let damage = canvas.render(&mut surface.renderer, output);
+                surface.needs_render = false;
+                surface.pending_damage.extend(damage);
+            }
+            if !surface.pending_damage.is_empty() && surface.present(&self.qh) {
+                rendered += 1;
+            }
+        }
+        rendered
+    }
+    pub fn surface_info(&self) -> Vec<SurfaceInfo> {
+        self.surfaces
+            .iter()
+            .map(|surface| SurfaceInfo {
+                output: surface.output_name.clone(),
+                width: surface.width,
+                height: surface.height,
+                scale: surface.scale(),
+                configured: surface.configured,
+            })
+            .collect()
+    }
+}
+impl OverlaySurface {
+    pub fn scale(&self) -> f32 {
+        self.fractional_scale.unwrap_or(self.scale_factor as f64) as f32
+    }
+    /// Reallocates the renderer and buffers for the current logical size and scale.
+    fn resize(&mut self, shm: &Shm) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        let scale = self.scale();
+        self.renderer = Renderer::with_scale(self.width, self.height, scale);
+        let (w, h) = (self.renderer.pixmap.width(), self.renderer.pixmap.height());
+        // Start a new pool, since the compositor may still be reading buffers in the old one
+        self.buffers.clear();
+        self.pool = Some(RawPool::new(w as usize * h as usize * 4 * 2, shm).unwrap());
+        self.pending_damage.clear();
+        let surface = self.layer_surface.wl_surface();
+        match &self.viewport {
+            Some(viewport) => {
+                surface.set_buffer_scale(1);
+                viewport.set_destination(self.width as i32, self.height as i32);
+            }
+            None => surface.set_buffer_scale(self.scale_factor),
+        }
+        self.needs_render = true;
+        log::info!(
+            "Resized renderer to {}x{} at scale {} ({}x{} pixels)",
+            self.width,
+            self.height,
+            scale,
+            w,
+            h
+        );
+    }
+    /// Brings a released buffer up to date with the pixmap and commits it with the pending
+    /// damage, asking for a frame callback. Returns whether anything was committed.
+    pub fn present(&mut self, qh: &QueueHandle<WaylandApp>) -> bool {
+        if !self.configured {
+            return false;
+        }
+        let Some(pool) = &mut self.pool else {
+            return false;
+        };
+        let pixmap = &self.renderer.pixmap;
+        let full = IntRect::from_xywh(0, 0, pixmap.width(), pixmap.height())
+            .expect("pixmap has a non-zero size");
+        let frame_len = pixmap.data().len();
+        for buffer in &mut self.buffers {
+            buffer.stale.extend_from_slice(&self.pending_damage);
+            buffer.stale = merge(std::mem::take(&mut buffer.stale), full);
+        }
+        let index = match self
+            .buffers
+            .iter()
+            .position(|b| !b.busy.load(Ordering::Relaxed))
+        {
+            Some(index) => index,
+            None if self.buffers.len() < MAX_BUFFERS => {
+                let offset = self.buffers.len() * frame_len;
+                if let Err(e) = pool.resize(offset + frame_len) {
+                    log::error!("Failed to grow buffer pool: {}", e);
+                    return false;
+                }
+                let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+                let busy = Arc::new(AtomicBool::new(false));
+                let buffer = pool.create_buffer(
+                    offset as i32,
+                    width,
+                    height,
+                    width * 4,
+                    wl_shm::Format::Argb8888,
+                    busy.clone(),
+                    qh,
+                );
+                self.buffers.push(SwapBuffer {
+                    buffer,
+                    offset,
+                    busy,
+                    stale: vec![full],
+                });
+                self.buffers.len() - 1
+            }
+            None => {
+                // Presented from the redraw that follows the next wl_buffer.release
+                log::debug!("All buffers are held by the compositor, deferring frame");
+                return false;
+            }
+        };
+        let swap = &mut self.buffers[index];
+        let canvas = &mut pool.mmap()[swap.offset..swap.offset + frame_len];
+        for rect in std::mem::take(&mut swap.stale) {
+            copy_bgra(pixmap, canvas, rect);
+        }
+        let surface = self.layer_surface.wl_surface();
+        surface.attach(Some(&swap.buffer), 0, 0);
+        swap.busy.store(true, Ordering::Relaxed);
+        for rect in merge(std::mem::take(&mut self.pending_damage), full) {
+            surface.damage_buffer(
+                rect.x(),
+                rect.y(),
+                rect.width() as i32,
+                rect.height() as i32,
+            );
+        }
+        surface.frame(qh, surface.clone());
+        surface.commit();
+        self.frame_pending = true;
+        true
+    }
+}
+/// Copies a region of the RGBA pixmap into an ARGB8888 (little-endian BGRA) buffer.
+fn copy_bgra(pixmap: &Pixmap, canvas: &mut [u8], rect: IntRect) {
+    let stride = pixmap.width() as usize * 4;
+    let (left, right) = (rect.left() as usize * 4, rect.right() as usize * 4);
+    for y in rect.top() as usize..rect.bottom() as usize {
+        let row = y * stride;
+        let src = &pixmap.data()[row + left..row + right];
+        let dst = &mut canvas[row + left..row + right];
+        for (dst, src) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            dst.copy_from_slice(&[src[2], src[1], src[0], src[3]]);
+        }
+    }
+}
+impl Drop for OverlaySurface {
+    fn drop(&mut self) {
+        if let Some(fractional) = &self.fractional {
+            fractional.destroy();
+        }
+        if let Some(viewport) = &self.viewport {
+            viewport.destroy();
+        }
     }
 }
 impl CompositorHandler for WaylandApp {
@@ -129,9 +484,20 @@ impl CompositorHandler for WaylandApp {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &wl_surface::WlSurface,
-        _: i32,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
     ) {
+        let Some(overlay) = self
+            .surfaces
+            .iter_mut()
+            .find(|s| s.layer_surface.wl_surface() == surface)
+        else {
+            return;
+        };
+        overlay.scale_factor = new_factor;
+        if overlay.fractional_scale.is_none() {
+            overlay.resize(&self.shm);
+        }
     }
     fn transform_changed(
         &mut self,
@@ -141,7 +507,21 @@ impl CompositorHandler for WaylandApp {
         _: wl_output::Transform,
     ) {
     }
-    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
+    fn frame(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        _: u32,
+    ) {
+        if let Some(overlay) = self
+            .surfaces
+            .iter_mut()
+            .find(|s| s.layer_surface.wl_surface() == surface)
+        {
+            overlay.frame_pending = false;
+        }
+    }
     fn surface_enter(
         &mut self,
         _: &Connection,
@@ -163,38 +543,150 @@ impl OutputHandler for WaylandApp {
     fn output_state(&mut self) -> &mut OutputState {
         &mut self.output_state
     }
-    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
-    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
-    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+    fn new_output(&mut self, _: &Connection, qh: &QueueHandle<Self>, output: wl_output::WlOutput) {
+        let name = self.output_state.info(&output).and_then(|info| info.name);
+        self.events.push(Event::OutputAdded { output: name });
+        self.create_layer_surface(qh, output);
+    }
+    fn update_output(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        let name = self.output_state.info(&output).and_then(|info| info.name);
+        if let Some(surface) = self.surfaces.iter_mut().find(|s| s.output == output) {
+            surface.output_name = name;
+            surface.needs_render = true;
+        }
+    }
+    fn output_destroyed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        let events = &mut self.events;
+        self.surfaces.retain(|s| {
+            let keep = s.output != output;
+            if !keep {
+                log::info!("Output {:?} removed, dropping its surface", s.output_name);
+                let output = s.output_name.clone();
+                events.push(Event::SurfaceClosed {
+                    output: output.clone(),
+                });
+                events.push(Event::OutputRemoved { output });
+            }
+            keep
+        });
+    }
 }
 impl LayerShellHandler for WaylandApp {
-    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface) {}
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, layer: &LayerSurface) {
+        let events = &mut self.events;
+        self.surfaces.retain(|s| {
+            let keep = &s.layer_surface != layer;
+            if !keep {
+                let output = s.output_name.clone();
+                events.push(Event::SurfaceClosed { output });
+            }
+            keep
+        });
+    }
     fn configure(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _layer: &LayerSurface,
+        layer: &LayerSurface,
         configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
+        let Some(surface) = self.surfaces.iter_mut().find(|s| &s.layer_surface == layer) else {
+            return;
+        };
         log::info!(
-            "Configure event received: size=({}, {})",
+            "Configure event received for output {:?}: size=({}, {})",
+            surface.output_name,
             configure.new_size.0,
             configure.new_size.1
         );
         let (w, h) = configure.new_size;
-        if w > 0 && h > 0 {
-            self.width = w;
-            self.height = h;
-            self.renderer = Renderer::new(w, h);
-            self.slot_pool = Some(SlotPool::new(w as usize * h as usize * 4, &self.shm).unwrap());
-            log::info!("Resized renderer to {}x{}", w, h);
-        }
-        self.configured = true;
-        self.needs_render = true;
+        if w > 0 && h > 0 && (w, h) != (surface.width, surface.height) {
+            surface.width = w;
+            surface.height = h;
+            surface.resize(&self.shm);
+        }
+        surface.configured = true;
+        surface.needs_render = true;
+        self.events.push(Event::SurfaceConfigured {
+            output: surface.output_name.clone(),
+            width: surface.width,
+            height: surface.height,
+        });
         log::info!("Surface configured and ready to render");
     }
 }
+impl SeatHandler for WaylandApp {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+    fn new_capability(
+        &mut self,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability != Capability::Pointer || self.pointer.is_some() {
+            return;
+        }
+        match self.seat_state.get_pointer(qh, &seat) {
+            Ok(pointer) => self.pointer = Some(pointer),
+            Err(e) => log::error!("Failed to get pointer: {}", e),
+        }
+    }
+    fn remove_capability(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Pointer
+            && let Some(pointer) = self.pointer.take()
+        {
+            pointer.release();
+        }
+    }
+    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+}
+impl PointerHandler for WaylandApp {
+    fn pointer_frame(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        self.pointer_events.extend_from_slice(events);
+    }
+}
+impl Dispatch<wl_buffer::WlBuffer, Arc<AtomicBool>> for WaylandApp {
+    fn event(
+        _: &mut Self,
+        _: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        busy: &Arc<AtomicBool>,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // Every dispatch ends in a redraw, which presents frames deferred for want of a buffer
+        if let wl_buffer::Event::Release = event {
+            busy.store(false, Ordering::Relaxed);
+        }
+    }
+}
 impl ShmHandler for WaylandApp {
     fn shm_state(&mut self) -> &mut Shm {
         &mut self.shm
@@ -205,6 +697,8 @@ delegate_output!(WaylandApp);
 delegate_shm!(WaylandApp);
 delegate_layer!(WaylandApp);
 delegate_registry!(WaylandApp);
+delegate_seat!(WaylandApp);
+delegate_pointer!(WaylandApp);
 impl ProvidesRegistryState for WaylandApp {
     fn registry(&mut self) -> &mut RegistryState {
         &mut self.registry_state
@@ -231,3 +725,27 @@ impl Dispatch<WlRegion, ()> for WaylandApp {
     ) {
     }
 }
+impl Dispatch<WpFractionalScaleV1, wl_surface::WlSurface> for WaylandApp {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        surface: &wl_surface::WlSurface,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event
+            && let Some(overlay) = state
+                .surfaces
+                .iter_mut()
+                .find(|s| s.layer_surface.wl_surface() == surface)
+        {
+            // The protocol sends the scale as a numerator over 120
+            overlay.fractional_scale = Some(scale as f64 / 120.0);
+            overlay.resize(&state.shm);
+        }
+    }
+}
+delegate_noop!(WaylandApp: ignore WpFractionalScaleManagerV1);
+delegate_noop!(WaylandApp: ignore WpViewporter);
+delegate_noop!(WaylandApp: ignore WpViewport);
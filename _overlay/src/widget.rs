@@ -0,0 +1,307 @@
+use anyhow::{Context, Result};
+use overlay_protocol::color::Brush;
+use overlay_protocol::coord::{Anchor, Coord};
+use overlay_protocol::draw::{
+    CircleParams, DrawOperation, ImageFilter, ImageParams, LineParams, LineSide, RectangleParams,
+    TextAlign, TextParams,
+};
+use overlay_protocol::widget::{
+    BadgeParams, FrameParams, Orientation, ProgressParams, ToastParams, Widget,
+};
+use serde::Deserialize;
+use std::path::Path;
+const TRANSPARENT: &str = "0x00000000";
+/// Styling defaults for widgets, loaded from a JSON theme file. Colors may be palette names.
+/// Missing fields keep their built-in values.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Theme {
+    /// Font family or file used for widget text.
+    pub font: Option<String>,
+    pub progress: ProgressTheme,
+    pub toast: ToastTheme,
+    pub frame: FrameTheme,
+    pub badge: BadgeTheme,
+}
+impl Theme {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("{}: invalid theme", path.display()))
+    }
+}
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ProgressTheme {
+    pub color: Brush,
+    pub background: Brush,
+    pub outline_width: f32,
+    pub outline_color: Brush,
+    pub tick_width: f32,
+    pub tick_color: Brush,
+}
+impl Default for ProgressTheme {
+    fn default() -> Self {
+        Self {
+            color: "0xFFFFFFFF".into(),
+            background: "0x80000000".into(),
+            outline_width: 0.0,
+            outline_color: TRANSPARENT.into(),
+            tick_width: 1.0,
+            tick_color: "0xFFFFFFFF".into(),
+        }
+    }
+}
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ToastTheme {
+    /// Corner of the surface that toasts stack from.
+    pub anchor: Anchor,
+    /// First layer of the stack; each further toast takes the next layer.
+    pub layer: i32,
+    pub max_stack: usize,
+    pub timeout_ms: Option<u64>,
+    pub width: f32,
+    pub height: f32,
+    /// Distance from the surface edges.
+    pub margin: f32,
+    /// Space between stacked toasts.
+    pub gap: f32,
+    pub padding: f32,
+    pub background: Brush,
+    pub outline_width: f32,
+    pub outline_color: Brush,
+    pub title_size: f32,
+    pub title_color: Brush,
+    pub body_size: f32,
+    pub body_color: Brush,
+}
+impl Default for ToastTheme {
+    fn default() -> Self {
+        Self {
+            anchor: Anchor::TopRight,
+            layer: 100,
+            max_stack: 4,
+            timeout_ms: Some(4000),
+            width: 320.0,
+            height: 72.0,
+            margin: 16.0,
+            gap: 8.0,
+            padding: 12.0,
+            background: "0xE0202020".into(),
+            outline_width: 1.0,
+            outline_color: "0xFF404040".into(),
+            title_size: 16.0,
+            title_color: "0xFFFFFFFF".into(),
+            body_size: 13.0,
+            body_color: "0xFFC0C0C0".into(),
+        }
+    }
+}
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct FrameTheme {
+    pub width: f32,
+    pub color: Brush,
+}
+impl Default for FrameTheme {
+    fn default() -> Self {
+        Self {
+            width: 4.0,
+            color: "0xFFFF0000".into(),
+        }
+    }
+}
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct BadgeTheme {
+    pub radius: f32,
+    pub background: Brush,
+    pub text_size: f32,
+    pub text_color: Brush,
+}
+impl Default for BadgeTheme {
+    fn default() -> Self {
+        Self {
+            radius: 12.0,
+            background: "0xFFE03030".into(),
+            text_size: 14.0,
+            text_color: "0xFFFFFFFF".into(),
+        }
+    }
+}
+/// Expands a widget into draw operations. `slot` is the position of a toast in its stack and
+/// is ignored by other widgets.
+pub fn expand(widget: Widget, theme: &Theme, slot: usize) -> Vec<DrawOperation> {
+    match widget {
+        Widget::Progress(p) => progress(p, theme),
+        Widget::Toast(p) => toast(p, theme, slot),
+        Widget::Frame(p) => frame(p, theme),
+        Widget::Badge(p) => badge(p, theme),
+    }
+}
+fn shift(coord: Coord, pixels: f32) -> Coord {
+    Coord {
+        percent: coord.percent,
+        pixels: coord.pixels + pixels,
+    }
+}
+fn progress(p: ProgressParams, theme: &Theme) -> Vec<DrawOperation> {
+    let style = &theme.progress;
+    let value = p.value.clamp(0.0, 1.0);
+    let (fill_x1, fill_y1) = match p.orientation {
+        Orientation::Horizontal => (p.x1, p.y1),
+        Orientation::Vertical => (p.x1, p.y2.lerp(p.y1, value)),
+    };
+    let (fill_x2, fill_y2) = match p.orientation {
+        Orientation::Horizontal => (p.x1.lerp(p.x2, value), p.y2),
+        Orientation::Vertical => (p.x2, p.y2),
+    };
+    let mut operations = vec![
+        DrawOperation::Rectangle(RectangleParams {
+            x1: p.x1,
+            y1: p.y1,
+            x2: p.x2,
+            y2: p.y2,
+            fill_color: p.background.unwrap_or_else(|| style.background.clone()),
+            outline_width: style.outline_width,
+            outline_color: style.outline_color.clone(),
+        }),
+        DrawOperation::Rectangle(RectangleParams {
+            x1: fill_x1,
+            y1: fill_y1,
+            x2: fill_x2,
+            y2: fill_y2,
+            fill_color: p.color.unwrap_or_else(|| style.color.clone()),
+            outline_width: 0.0,
+            outline_color: TRANSPARENT.into(),
+        }),
+    ];
+    for i in 1..p.ticks {
+        let t = i as f32 / p.ticks as f32;
+        let (x1, y1, x2, y2) = match p.orientation {
+            Orientation::Horizontal => {
+                let x = p.x1.lerp(p.x2, t);
+                (x, p.y1, x, p.y2)
+            }
+            Orientation::Vertical => {
+                let y = p.y1.lerp(p.y2, t);
+                (p.x1, y, p.x2, y)
+            }
+        };
+        operations.push(DrawOperation::Line(LineParams {
+            x1,
+            y1,
+            x2,
+            y2,
+            width: style.tick_width,
+            side: LineSide::Center,
+            color: style.tick_color.clone(),
+        }));
+    }
+    operations
+}
+fn toast(p: ToastParams, theme: &Theme, slot: usize) -> Vec<DrawOperation> {
+    let style = &theme.toast;
+    // Coordinates are relative to the anchor, so place the box on the anchor's side of
+    // each axis and stack away from the edge it sits on
+    let (fx, fy) = style.anchor.fractions();
+    let offset = slot as f32 * (style.height + style.gap);
+    let left = -fx * style.width + (1.0 - 2.0 * fx) * style.margin;
+    let top = -fy * style.height + (1.0 - 2.0 * fy) * style.margin;
+    let top = if fy == 1.0 {
+        top - offset
+    } else {
+        top + offset
+    };
+    let mut operations = vec![DrawOperation::Rectangle(RectangleParams {
+        x1: Coord::pixels(left),
+        y1: Coord::pixels(top),
+        x2: Coord::pixels(left + style.width),
+        y2: Coord::pixels(top + style.height),
+        fill_color: style.background.clone(),
+        outline_width: style.outline_width,
+        outline_color: style.outline_color.clone(),
+    })];
+    let mut text_left = left + style.padding;
+    if let Some(icon) = p.icon {
+        let size = style.height - 2.0 * style.padding;
+        operations.push(DrawOperation::Image(ImageParams {
+            path: Some(icon),
+            data: None,
+            x: Coord::pixels(text_left),
+            y: Coord::pixels(top + style.padding),
+            width: Some(Coord::pixels(size)),
+            height: Some(Coord::pixels(size)),
+            filter: ImageFilter::default(),
+            opacity: None,
+        }));
+        text_left += size + style.padding;
+    }
+    let max_width = Some(Coord::pixels(
+        left + style.width - style.padding - text_left,
+    ));
+    operations.push(DrawOperation::Text(TextParams {
+        x: Coord::pixels(text_left),
+        y: Coord::pixels(top + style.padding),
+        text: p.title,
+        font: theme.font.clone(),
+        size: style.title_size,
+        color: style.title_color.clone(),
+        align: TextAlign::Left,
+        max_width,
+    }));
+    if let Some(body) = p.body {
+        operations.push(DrawOperation::Text(TextParams {
+            x: Coord::pixels(text_left),
+            y: Coord::pixels(top + style.padding + style.title_size * 1.4),
+            text: body,
+            font: theme.font.clone(),
+            size: style.body_size,
+            color: style.body_color.clone(),
+            align: TextAlign::Left,
+            max_width,
+        }));
+    }
+    operations
+}
+fn frame(p: FrameParams, theme: &Theme) -> Vec<DrawOperation> {
+    let full = Coord {
+        percent: 100.0,
+        pixels: 0.0,
+    };
+    // Rectangle outlines are drawn inside the edges, so the frame reaches the screen border
+    vec![DrawOperation::Rectangle(RectangleParams {
+        x1: p.x1.unwrap_or_default(),
+        y1: p.y1.unwrap_or_default(),
+        x2: p.x2.unwrap_or(full),
+        y2: p.y2.unwrap_or(full),
+        fill_color: TRANSPARENT.into(),
+        outline_width: p.width.unwrap_or(theme.frame.width),
+        outline_color: p.color.unwrap_or_else(|| theme.frame.color.clone()),
+    })]
+}
+fn badge(p: BadgeParams, theme: &Theme) -> Vec<DrawOperation> {
+    let style = &theme.badge;
+    vec![
+        DrawOperation::Circle(CircleParams {
+            x: p.x,
+            y: p.y,
+            radius: style.radius,
+            fill_color: p.color.unwrap_or_else(|| style.background.clone()),
+            outline_width: 0.0,
+            outline_color: TRANSPARENT.into(),
+        }),
+        // Text is positioned by the top of its line, which is about 1.2 sizes tall
+        DrawOperation::Text(TextParams {
+            x: p.x,
+            y: shift(p.y, -style.text_size * 0.6),
+            text: p.text,
+            font: theme.font.clone(),
+            size: style.text_size,
+            color: style.text_color.clone(),
+            align: TextAlign::Center,
+            max_width: None,
+        }),
+    ]
+}